@@ -0,0 +1,654 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::format;
+
+use super::command::*;
+use super::error::ProtocolError;
+use super::packet::Packet;
+use super::tlv::*;
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub enum ChannelClass {
+  Buffered = 0,
+  Stream = 1,
+  Datagram = 2,
+  Pool = 3,
+}
+impl ChannelClass {
+  pub fn from_u32(val: u32) -> Option<ChannelClass> {
+    match val {
+      0 => Some(ChannelClass::Buffered),
+      1 => Some(ChannelClass::Stream),
+      2 => Some(ChannelClass::Datagram),
+      3 => Some(ChannelClass::Pool),
+      _ => None,
+    }
+  }
+}
+impl From<ChannelClass> for u32 {
+  fn from(class: ChannelClass) -> u32 {
+    class as u32
+  }
+}
+
+// bytes a channel may have sent but not yet had acknowledged
+pub const DEFAULT_CHANNEL_WINDOW: usize = 256 * 1024;
+// produced data held back per channel before producers are pushed back on
+pub const DEFAULT_CHANNEL_QUEUE_LIMIT: usize = 1024 * 1024;
+pub const CHANNEL_CHUNK_SIZE: usize = 64 * 1024;
+// largest a MemoryFile grows unless told otherwise
+pub const DEFAULT_MEMORY_FILE_LIMIT: usize = 16 * 1024 * 1024;
+// most a BufferedChannel holds unread unless told otherwise
+pub const DEFAULT_BUFFERED_CHANNEL_LIMIT: usize = 16 * 1024 * 1024;
+
+pub const SEEK_SET: u32 = 0;
+pub const SEEK_CUR: u32 = 1;
+pub const SEEK_END: u32 = 2;
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub enum SeekFrom {
+  Start(u64),
+  Current(i64),
+  End(i64),
+}
+impl SeekFrom {
+  // SeekOffset travels as a uint but meterpreter treats it as signed
+  pub fn from_whence(whence: u32, offset: u32) -> Option<SeekFrom> {
+    let offset = offset as i32;
+    match whence {
+      SEEK_SET if offset >= 0 => Some(SeekFrom::Start(offset as u64)),
+      SEEK_CUR => Some(SeekFrom::Current(offset as i64)),
+      SEEK_END => Some(SeekFrom::End(offset as i64)),
+      _ => None,
+    }
+  }
+}
+
+// whatever sits behind a channel, a file, process pipe, socket...; the
+// defaults refuse, so backends only implement what their class supports
+pub trait ChannelBackend {
+  fn class(&self) -> ChannelClass;
+  fn write(&mut self, _data: &[u8]) -> Result<usize, CommandError> {
+    Err(not_supported("write"))
+  }
+  // at most len bytes, fewer (or none) is fine
+  fn read(&mut self, _len: usize) -> Result<Vec<u8>, CommandError> {
+    Err(not_supported("read"))
+  }
+  fn eof(&self) -> bool {
+    false
+  }
+  fn close(&mut self) -> Result<(), CommandError> {
+    Ok(())
+  }
+  // pool backends hand themselves back here
+  fn as_pool(&mut self) -> Option<&mut dyn PoolBackend> {
+    None
+  }
+}
+
+// random access backends, files and the like
+pub trait PoolBackend: ChannelBackend {
+  // returns the new position
+  fn seek(&mut self, pos: SeekFrom) -> Result<u64, CommandError>;
+  fn tell(&mut self) -> Result<u64, CommandError> {
+    self.seek(SeekFrom::Current(0))
+  }
+}
+
+// builds backends for one ChannelType from the core_channel_open request
+pub trait ChannelFactory {
+  fn open(&mut self, request: &Packet) -> Result<Box<dyn ChannelBackend>, CommandError>;
+}
+impl<F> ChannelFactory for F
+  where F: FnMut(&Packet) -> Result<Box<dyn ChannelBackend>, CommandError>
+{
+  fn open(&mut self, request: &Packet) -> Result<Box<dyn ChannelBackend>, CommandError> {
+    self(request)
+  }
+}
+
+// a loopback queue, reads hand back what was written
+#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct BufferedChannel {
+  buffer: Vec<u8>,
+  closed: bool,
+  // writes that would hold more than this unread are refused
+  limit: usize,
+}
+impl Default for BufferedChannel {
+  fn default() -> Self {
+    BufferedChannel::new()
+  }
+}
+impl BufferedChannel {
+  pub fn new() -> Self {
+    BufferedChannel {
+      buffer: Vec::new(),
+      closed: false,
+      limit: DEFAULT_BUFFERED_CHANNEL_LIMIT,
+    }
+  }
+  pub fn buffered(&self) -> usize {
+    self.buffer.len()
+  }
+  pub fn limit(&self) -> usize {
+    self.limit
+  }
+  pub fn set_limit(mut self, bytes: usize) -> Self {
+    self.set_limit_ref(bytes);
+    self
+  }
+  pub fn set_limit_ref(&mut self, bytes: usize) {
+    self.limit = bytes;
+  }
+  // no more writes, reads drain what is left and then report eof
+  pub fn shutdown(&mut self) {
+    self.closed = true;
+  }
+}
+impl ChannelBackend for BufferedChannel {
+  fn class(&self) -> ChannelClass {
+    ChannelClass::Buffered
+  }
+  fn write(&mut self, data: &[u8]) -> Result<usize, CommandError> {
+    if self.closed {
+      return Err(CommandError::new(ERROR_INVALID_PARAMETER, "channel is shut down"));
+    }
+    if data.len() > self.limit.saturating_sub(self.buffer.len()) {
+      return Err(CommandError::new(ERROR_NOT_ENOUGH_MEMORY, "channel buffer is full"));
+    }
+    self.buffer.extend_from_slice(data);
+    Ok(data.len())
+  }
+  fn read(&mut self, len: usize) -> Result<Vec<u8>, CommandError> {
+    let len = core::cmp::min(len, self.buffer.len());
+    let rest = self.buffer.split_off(len);
+    Ok(core::mem::replace(&mut self.buffer, rest))
+  }
+  fn eof(&self) -> bool {
+    self.closed && self.buffer.is_empty()
+  }
+  fn close(&mut self) -> Result<(), CommandError> {
+    self.shutdown();
+    Ok(())
+  }
+}
+
+// file-like pool backend held in memory
+#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct MemoryFile {
+  data: Vec<u8>,
+  pos: usize,
+  // writes may not take the file past this, wherever the peer seeks to
+  limit: usize,
+}
+impl Default for MemoryFile {
+  fn default() -> Self {
+    MemoryFile::new(Vec::new())
+  }
+}
+impl MemoryFile {
+  pub fn new(data: Vec<u8>) -> Self {
+    MemoryFile {
+      data,
+      pos: 0,
+      limit: DEFAULT_MEMORY_FILE_LIMIT,
+    }
+  }
+  pub fn data(&self) -> &Vec<u8> {
+    &self.data
+  }
+  pub fn limit(&self) -> usize {
+    self.limit
+  }
+  pub fn set_limit(mut self, bytes: usize) -> Self {
+    self.set_limit_ref(bytes);
+    self
+  }
+  pub fn set_limit_ref(&mut self, bytes: usize) {
+    self.limit = bytes;
+  }
+}
+impl ChannelBackend for MemoryFile {
+  fn class(&self) -> ChannelClass {
+    ChannelClass::Pool
+  }
+  // writes past the end grow the file, zero filling any gap
+  fn write(&mut self, data: &[u8]) -> Result<usize, CommandError> {
+    let end = match self.pos.checked_add(data.len()) {
+      Some(end) if end <= self.limit => end,
+      _ => return Err(CommandError::new(ERROR_NOT_ENOUGH_MEMORY, "write past the file size limit")),
+    };
+    if end > self.data.len() {
+      self.data.resize(end, 0x00);
+    }
+    self.data[self.pos..end].copy_from_slice(data);
+    self.pos = end;
+    Ok(data.len())
+  }
+  fn read(&mut self, len: usize) -> Result<Vec<u8>, CommandError> {
+    let start = core::cmp::min(self.pos, self.data.len());
+    let end = core::cmp::min(start.saturating_add(len), self.data.len());
+    self.pos = end;
+    Ok(self.data[start..end].to_vec())
+  }
+  fn eof(&self) -> bool {
+    self.pos >= self.data.len()
+  }
+  fn as_pool(&mut self) -> Option<&mut dyn PoolBackend> {
+    Some(self)
+  }
+}
+impl PoolBackend for MemoryFile {
+  fn seek(&mut self, pos: SeekFrom) -> Result<u64, CommandError> {
+    let target = match pos {
+      SeekFrom::Start(offset) => offset as i64,
+      SeekFrom::Current(offset) => (self.pos as i64).saturating_add(offset),
+      SeekFrom::End(offset) => (self.data.len() as i64).saturating_add(offset),
+    };
+    if target < 0 {
+      return Err(CommandError::new(ERROR_INVALID_PARAMETER, "seek before the start of the file"));
+    }
+    self.pos = target as usize;
+    Ok(self.pos as u64)
+  }
+}
+
+pub struct Channel {
+  id: u32,
+  channel_type: String,
+  class: ChannelClass,
+  parent: Option<u32>,
+  flags: u32,
+  backend: Box<dyn ChannelBackend>,
+  // data produced by the channel and not yet delivered to the other end
+  queue: Vec<u8>,
+  interactive: bool,
+  in_flight: usize,
+}
+impl Channel {
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+  pub fn channel_type(&self) -> &str {
+    &self.channel_type
+  }
+  pub fn class(&self) -> ChannelClass {
+    self.class
+  }
+  pub fn parent(&self) -> Option<u32> {
+    self.parent
+  }
+  pub fn flags(&self) -> u32 {
+    self.flags
+  }
+  pub fn backend(&self) -> &dyn ChannelBackend {
+    self.backend.as_ref()
+  }
+  pub fn mut_backend(&mut self) -> &mut dyn ChannelBackend {
+    self.backend.as_mut()
+  }
+  pub fn interactive(&self) -> bool {
+    self.interactive
+  }
+  pub fn queued(&self) -> usize {
+    self.queue.len()
+  }
+  pub fn in_flight(&self) -> usize {
+    self.in_flight
+  }
+}
+impl core::fmt::Debug for Channel {
+  fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    f.debug_struct("Channel")
+      .field("id", &self.id)
+      .field("channel_type", &self.channel_type)
+      .field("class", &self.class)
+      .field("parent", &self.parent)
+      .field("flags", &self.flags)
+      .field("queued", &self.queue.len())
+      .field("interactive", &self.interactive)
+      .field("in_flight", &self.in_flight)
+      .finish()
+  }
+}
+
+// open channels by id, and the factories that create them by ChannelType
+pub struct ChannelManager {
+  next_id: u32,
+  channels: BTreeMap<u32, Channel>,
+  factories: BTreeMap<String, Box<dyn ChannelFactory>>,
+  window: usize,
+  queue_limit: usize,
+  // our core_channel_write requests by RequestId, as (channel, length)
+  writes: BTreeMap<String, (u32, usize)>,
+  // the channel next_outbound served last, the search resumes after it
+  cursor: u32,
+}
+impl Default for ChannelManager {
+  fn default() -> Self {
+    ChannelManager::new()
+  }
+}
+impl ChannelManager {
+  pub fn new() -> Self {
+    ChannelManager {
+      next_id: 1,
+      channels: BTreeMap::new(),
+      factories: BTreeMap::new(),
+      window: DEFAULT_CHANNEL_WINDOW,
+      queue_limit: DEFAULT_CHANNEL_QUEUE_LIMIT,
+      writes: BTreeMap::new(),
+      cursor: 0,
+    }
+  }
+  pub fn window(&self) -> usize {
+    self.window
+  }
+  pub fn set_window(mut self, bytes: usize) -> Self {
+    self.set_window_ref(bytes);
+    self
+  }
+  pub fn set_window_ref(&mut self, bytes: usize) {
+    self.window = bytes;
+  }
+  pub fn queue_limit(&self) -> usize {
+    self.queue_limit
+  }
+  pub fn set_queue_limit(mut self, bytes: usize) -> Self {
+    self.set_queue_limit_ref(bytes);
+    self
+  }
+  pub fn set_queue_limit_ref(&mut self, bytes: usize) {
+    self.queue_limit = bytes;
+  }
+  pub fn register<F>(mut self, channel_type: &str, factory: F) -> Self
+    where F: ChannelFactory + 'static
+  {
+    self.register_ref(channel_type, factory);
+    self
+  }
+  pub fn register_ref<F>(&mut self, channel_type: &str, factory: F)
+    where F: ChannelFactory + 'static
+  {
+    self.factories.insert(channel_type.to_string(), Box::new(factory));
+  }
+  pub fn len(&self) -> usize {
+    self.channels.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.channels.is_empty()
+  }
+  pub fn ids(&self) -> Vec<u32> {
+    self.channels.keys().cloned().collect()
+  }
+  pub fn get(&self, id: u32) -> Option<&Channel> {
+    self.channels.get(&id)
+  }
+  pub fn get_mut(&mut self, id: u32) -> Option<&mut Channel> {
+    self.channels.get_mut(&id)
+  }
+  // adds a channel the agent opened itself, returning its id
+  pub fn insert(&mut self, channel_type: &str, backend: Box<dyn ChannelBackend>, parent: Option<u32>, flags: u32)
+    -> Result<u32, CommandError>
+  {
+    if let Some(parent) = parent {
+      self.lookup(parent)?;
+    }
+    let id = self.allocate_id();
+    self.channels.insert(id, Channel {
+      id,
+      channel_type: channel_type.to_string(),
+      class: backend.class(),
+      parent,
+      flags,
+      backend,
+      queue: Vec::new(),
+      interactive: false,
+      in_flight: 0,
+    });
+    Ok(id)
+  }
+  pub fn close(&mut self, id: u32) -> Result<(), CommandError> {
+    let mut channel = self.channels.remove(&id).ok_or_else(|| no_channel(id))?;
+    self.writes.retain(|_, write| write.0 != id);
+    channel.backend.close()
+  }
+  pub fn set_interactive(&mut self, id: u32, interactive: bool) -> Result<(), CommandError> {
+    self.lookup(id)?.interactive = interactive;
+    Ok(())
+  }
+  // queues data a channel produced outside of pump, returning how much fit
+  // under the queue limit; the caller holds on to the rest
+  pub fn push(&mut self, id: u32, data: &[u8]) -> Result<usize, CommandError> {
+    let limit = self.queue_limit;
+    let channel = self.lookup(id)?;
+    let accepted = core::cmp::min(data.len(), limit.saturating_sub(channel.queue.len()));
+    channel.queue.extend_from_slice(&data[..accepted]);
+    Ok(accepted)
+  }
+  // reads whatever interactive backends have ready into their queues,
+  // returning the number of bytes gathered
+  pub fn pump(&mut self) -> usize {
+    let limit = self.queue_limit;
+    let mut total = 0;
+    for channel in self.channels.values_mut().filter(|c| c.interactive) {
+      let room = core::cmp::min(limit.saturating_sub(channel.queue.len()), CHANNEL_CHUNK_SIZE);
+      if room == 0 {
+        continue;
+      }
+      // backends that cannot be read from just push their data instead
+      if let Ok(data) = channel.backend.read(room) {
+        total += data.len();
+        channel.queue.extend_from_slice(&data);
+      }
+    }
+    total
+  }
+  // the next core_channel_write request for an interactive channel whose
+  // window has room, ready to be stamped and sent; nothing is taken from the
+  // channel until the sent request is passed to track
+  pub fn next_outbound(&self) -> Option<Packet> {
+    let window = self.window;
+    let ready = |c: &Channel| c.interactive && !c.queue.is_empty() && c.in_flight < window;
+    // round robin from the cursor so one busy channel can't starve the rest
+    let id = self.channels.range(self.cursor.saturating_add(1)..)
+      .chain(self.channels.range(..=self.cursor))
+      .find(|(_, c)| ready(c))
+      .map(|(id, _)| *id)?;
+    let channel = self.channels.get(&id)?;
+    let len = core::cmp::min(channel.queue.len(), core::cmp::min(window - channel.in_flight, CHANNEL_CHUNK_SIZE));
+    let packet = Packet::create(TlvPacketType::Request, Tlv::string(TlvType::Method, "core_channel_write").ok()?)
+      .add_tlv(Tlv::uint(TlvType::ChannelId, id).ok()?)
+      .add_tlv(Tlv::uint(TlvType::Length, len as u32).ok()?)
+      .add_tlv(channel_data(channel.flags, channel.queue[..len].to_vec()).ok()?);
+    Some(packet)
+  }
+  // commits a sent write from next_outbound: its data leaves the queue, the
+  // window shrinks until the response comes back, and the next channel is up
+  pub fn track(&mut self, request: &Packet) -> Result<(), ProtocolError> {
+    let request_id = request.request_id().ok_or(ProtocolError::MissingTlv(TlvType::RequestId))?;
+    let id = request.require_tlv(TlvType::ChannelId)?.as_u32()?;
+    let len = request.require_tlv(TlvType::Length)?.as_u32()? as usize;
+    if let Some(channel) = self.channels.get_mut(&id) {
+      let taken = core::cmp::min(len, channel.queue.len());
+      channel.queue.drain(..taken);
+      channel.in_flight += len;
+    }
+    self.cursor = id;
+    self.writes.insert(request_id.to_string(), (id, len));
+    Ok(())
+  }
+  // consumes responses to tracked writes, whatever their result
+  pub fn acknowledge(&mut self, response: &Packet) -> bool {
+    match *response.header().get_type() {
+      TlvPacketType::Response | TlvPacketType::PlainResponse => {},
+      _ => return false,
+    }
+    let (id, len) = match response.request_id().and_then(|rid| self.writes.remove(rid)) {
+      Some(write) => write,
+      None => return false,
+    };
+    if let Some(channel) = self.channels.get_mut(&id) {
+      channel.in_flight = channel.in_flight.saturating_sub(len);
+    }
+    true
+  }
+  // ids are never 0 and are not reused while a channel still holds them
+  fn allocate_id(&mut self) -> u32 {
+    loop {
+      let id = self.next_id;
+      self.next_id = self.next_id.wrapping_add(1);
+      if id != 0 && !self.channels.contains_key(&id) {
+        return id;
+      }
+    }
+  }
+  fn lookup(&mut self, id: u32) -> Result<&mut Channel, CommandError> {
+    self.channels.get_mut(&id).ok_or_else(|| no_channel(id))
+  }
+  // the core_channel_* commands, None for any other method
+  pub fn handle(&mut self, request: &Packet, response: &mut Packet) -> Option<Result<(), CommandError>> {
+    let method = request.find_tlv(TlvType::Method)?.as_str().ok()?;
+    let result = match method {
+      "core_channel_open" => self.open(request, response),
+      "core_channel_write" => self.write(request, response),
+      "core_channel_read" => self.read(request, response),
+      "core_channel_close" => channel_id(request).and_then(|id| self.close(id)),
+      "core_channel_eof" => self.eof(request, response),
+      "core_channel_seek" => self.seek(request),
+      "core_channel_tell" => self.tell(request, response),
+      "core_channel_interact" => self.interact(request),
+      _ => return None,
+    };
+    Some(result)
+  }
+  fn open(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError> {
+    let channel_type = request.require_tlv(TlvType::ChannelType)?.as_str()?;
+    let parent = match request.find_tlv(TlvType::ChannelParentId) {
+      Some(tlv) => Some(tlv.as_u32()?),
+      None => None,
+    };
+    let flags = match request.find_tlv(TlvType::Flags) {
+      Some(tlv) => tlv.as_u32()?,
+      None => 0,
+    };
+    let class = match request.find_tlv(TlvType::ChannelClass) {
+      Some(tlv) => Some(tlv.as_u32()?),
+      None => None,
+    };
+    // everything that can fail is checked before the backend exists
+    if let Some(parent) = parent {
+      self.lookup(parent)?;
+    }
+    let factory = self.factories.get_mut(channel_type)
+      .ok_or_else(|| CommandError::new(ERROR_NOT_SUPPORTED, format!("unknown channel type {}", channel_type)))?;
+    let mut backend = factory.open(request)?;
+    if class.is_some_and(|class| class != u32::from(backend.class())) {
+      // a failed close has nothing to add to the error below
+      let _ = backend.close();
+      return Err(CommandError::new(ERROR_INVALID_PARAMETER, "channel type does not support that class"));
+    }
+    let id = self.insert(channel_type, backend, parent, flags)?;
+    response.add_tlv_ref(Tlv::uint(TlvType::ChannelId, id)?);
+    Ok(())
+  }
+  fn write(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError> {
+    let channel = self.lookup(channel_id(request)?)?;
+    let mut data = request.find_bytes(TlvType::ChanneData)
+      .ok_or(ProtocolError::MissingTlv(TlvType::ChanneData))?;
+    // Length may ask for less than the data carried
+    if let Some(length) = request.find_tlv(TlvType::Length) {
+      data = &data[..core::cmp::min(length.as_u32()? as usize, data.len())];
+    }
+    let written = channel.backend.write(data)?;
+    response.add_tlv_ref(Tlv::uint(TlvType::Length, written as u32)?);
+    Ok(())
+  }
+  fn read(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError> {
+    let channel = self.lookup(channel_id(request)?)?;
+    // the peer picks Length, so one read never answers with more than a chunk
+    let length = core::cmp::min(request.require_tlv(TlvType::Length)?.as_u32()? as usize, CHANNEL_CHUNK_SIZE);
+    // anything already queued goes first
+    let queued = core::cmp::min(length, channel.queue.len());
+    let rest = channel.queue.split_off(queued);
+    let mut data = core::mem::replace(&mut channel.queue, rest);
+    if data.len() < length {
+      match channel.backend.read(length - data.len()) {
+        Ok(more) => data.extend_from_slice(&more),
+        Err(err) if data.is_empty() => return Err(err),
+        Err(_) => {},
+      }
+    }
+    response.add_tlv_ref(Tlv::uint(TlvType::Length, data.len() as u32)?);
+    response.add_tlv_ref(channel_data(channel.flags, data)?);
+    Ok(())
+  }
+  fn interact(&mut self, request: &Packet) -> Result<(), CommandError> {
+    let id = channel_id(request)?;
+    let enable = request.require_tlv(TlvType::Bool)?.as_bool()?;
+    self.set_interactive(id, enable)
+  }
+  fn seek(&mut self, request: &Packet) -> Result<(), CommandError> {
+    let pool = pool(self.lookup(channel_id(request)?)?)?;
+    let offset = request.require_tlv(TlvType::SeekOffset)?.as_u32()?;
+    let whence = request.require_tlv(TlvType::SeekWhence)?.as_u32()?;
+    let pos = SeekFrom::from_whence(whence, offset)
+      .ok_or_else(|| CommandError::new(ERROR_INVALID_PARAMETER, "bad seek"))?;
+    pool.seek(pos)?;
+    Ok(())
+  }
+  fn tell(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError> {
+    let pool = pool(self.lookup(channel_id(request)?)?)?;
+    let pos = pool.tell()?;
+    if pos > u32::MAX as u64 {
+      return Err(CommandError::new(ERROR_INVALID_PARAMETER, "position does not fit in SeekPos"));
+    }
+    response.add_tlv_ref(Tlv::uint(TlvType::SeekPos, pos as u32)?);
+    Ok(())
+  }
+  fn eof(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError> {
+    let channel = self.lookup(channel_id(request)?)?;
+    response.add_tlv_ref(Tlv::bool(TlvType::Bool, channel.backend.eof())?);
+    Ok(())
+  }
+}
+impl core::fmt::Debug for ChannelManager {
+  fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    f.debug_struct("ChannelManager")
+      .field("next_id", &self.next_id)
+      .field("channels", &self.channels)
+      .field("factories", &self.factories.keys().collect::<Vec<_>>())
+      .field("window", &self.window)
+      .field("queue_limit", &self.queue_limit)
+      .field("writes", &self.writes)
+      .finish()
+  }
+}
+
+// compressed when the channel was opened with CHANNEL_FLAG_COMPRESS
+#[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+fn channel_data(flags: u32, data: Vec<u8>) -> Result<Tlv, ProtocolError> {
+  let tlv = Tlv::raw(TlvType::ChanneData, data)?;
+  #[cfg(feature = "compression")]
+  let tlv = match flags & CHANNEL_FLAG_COMPRESS {
+    0 => tlv,
+    _ => tlv.compress()?,
+  };
+  Ok(tlv)
+}
+fn channel_id(request: &Packet) -> Result<u32, CommandError> {
+  Ok(request.require_tlv(TlvType::ChannelId)?.as_u32()?)
+}
+fn pool(channel: &mut Channel) -> Result<&mut dyn PoolBackend, CommandError> {
+  channel.backend.as_pool().ok_or_else(|| not_supported("seeking"))
+}
+fn no_channel(id: u32) -> CommandError {
+  CommandError::new(ERROR_NOT_FOUND, format!("no channel {}", id))
+}
+fn not_supported(op: &str) -> CommandError {
+  CommandError::new(ERROR_NOT_SUPPORTED, format!("channel does not support {}", op))
+}
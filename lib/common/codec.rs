@@ -0,0 +1,101 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+#[cfg(feature = "crypto")]
+use zeroize::Zeroize;
+
+#[cfg(feature = "crypto")]
+use super::crypto::SymetricKey;
+use super::error::ProtocolError;
+use super::packet::*;
+use super::utils::Rng;
+
+#[derive(Clone,Eq,PartialEq,Hash)]
+pub enum CodecState {
+  Plaintext,
+  #[cfg(feature = "crypto")]
+  Encrypted(SymetricKey),
+}
+// keeps the session key out of logs
+impl fmt::Debug for CodecState {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CodecState::Plaintext => write!(f, "Plaintext"),
+      #[cfg(feature = "crypto")]
+      CodecState::Encrypted(_) => write!(f, "Encrypted(<redacted>)"),
+    }
+  }
+}
+#[cfg(feature = "crypto")]
+impl Drop for CodecState {
+  fn drop(&mut self) {
+    if let CodecState::Encrypted(ref mut key) = *self {
+      key.zeroize();
+    }
+  }
+}
+
+// turns packets into wire bytes and back: a fresh xor key on every packet,
+// plus aes256 payloads once a session key has been negotiated
+#[derive(Clone,Debug,Eq,PartialEq,Hash)]
+pub struct PacketCodec {
+  state: CodecState,
+}
+impl Default for PacketCodec {
+  fn default() -> Self {
+    PacketCodec::new()
+  }
+}
+impl PacketCodec {
+  pub fn new() -> Self {
+    PacketCodec {
+      state: CodecState::Plaintext,
+    }
+  }
+  pub fn state(&self) -> &CodecState {
+    &self.state
+  }
+  pub fn set_state(mut self, state: CodecState) -> Self {
+    self.set_state_ref(state);
+    self
+  }
+  pub fn set_state_ref(&mut self, state: CodecState) {
+    self.state = state;
+  }
+  pub fn is_encrypted(&self) -> bool {
+    self.state != CodecState::Plaintext
+  }
+  pub fn encode<R>(&self, pkt: Packet, rng: &mut R) -> Vec<u8>
+    where R: Rng
+  {
+    let key = random_xor_key(rng);
+    let header = pkt.header().set_key(key);
+    let pkt = pkt.set_header(header);
+    let mut vec: Vec<u8> = match self.state {
+      CodecState::Plaintext => pkt.into(),
+      #[cfg(feature = "crypto")]
+      CodecState::Encrypted(ref sym) => pkt.encode_encrypted(sym, rng),
+    };
+    xor_in_place(&key, &mut vec[XOR_KEY_SIZE..]);
+    vec
+  }
+  pub fn decode(&self, val: &[u8]) -> Result<Packet, ProtocolError> {
+    ProtocolError::check_len(val, XOR_KEY_SIZE)?;
+    let mut key: XorKey = [0x00; XOR_KEY_SIZE];
+    key.copy_from_slice(&val[..XOR_KEY_SIZE]);
+    let mut vec: Vec<u8> = val.to_vec();
+    xor_in_place(&key, &mut vec[XOR_KEY_SIZE..]);
+    match self.state {
+      CodecState::Plaintext => {
+        let pkt = Packet::try_from(&vec[..])?;
+        match pkt.header().enc_flags() {
+          ENC_FLAG_NONE => Ok(pkt),
+          flags => Err(ProtocolError::UnknownEncryption(flags)),
+        }
+      },
+      #[cfg(feature = "crypto")]
+      CodecState::Encrypted(ref sym) => Packet::decode_encrypted(&vec, sym),
+    }
+  }
+}
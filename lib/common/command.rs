@@ -0,0 +1,105 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::format;
+
+use super::error::ProtocolError;
+use super::packet::*;
+use super::tlv::*;
+
+pub use super::result::*;
+
+// a failed command, sent back as an Exception group
+#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct CommandError {
+  code: u32,
+  message: String,
+}
+impl CommandError {
+  pub fn new<S>(code: u32, message: S) -> Self
+    where S: Into<String>
+  {
+    CommandError {
+      code,
+      message: message.into(),
+    }
+  }
+  pub fn code(&self) -> u32 {
+    self.code
+  }
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+}
+// missing or mistyped arguments are the caller's fault
+impl From<ProtocolError> for CommandError {
+  fn from(err: ProtocolError) -> Self {
+    CommandError::new(ERROR_INVALID_PARAMETER, format!("{}", err))
+  }
+}
+
+pub trait CommandHandler {
+  // reads its arguments from request and adds any results to response,
+  // which already carries the Method, RequestId and a success Result
+  fn handle(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError>;
+}
+impl<F> CommandHandler for F
+  where F: FnMut(&Packet, &mut Packet) -> Result<(), CommandError>
+{
+  fn handle(&mut self, request: &Packet, response: &mut Packet) -> Result<(), CommandError> {
+    self(request, response)
+  }
+}
+
+// routes requests to handlers by their Method tlv
+pub struct CommandRegistry {
+  handlers: BTreeMap<String, Box<dyn CommandHandler>>,
+}
+impl Default for CommandRegistry {
+  fn default() -> Self {
+    CommandRegistry::new()
+  }
+}
+impl CommandRegistry {
+  pub fn new() -> Self {
+    CommandRegistry {
+      handlers: BTreeMap::new(),
+    }
+  }
+  pub fn register<H>(mut self, method: &str, handler: H) -> Self
+    where H: CommandHandler + 'static
+  {
+    self.register_ref(method, handler);
+    self
+  }
+  // replaces any handler already registered for method
+  pub fn register_ref<H>(&mut self, method: &str, handler: H)
+    where H: CommandHandler + 'static
+  {
+    self.handlers.insert(String::from(method), Box::new(handler));
+  }
+  pub fn remove(&mut self, method: &str) -> bool {
+    self.handlers.remove(method).is_some()
+  }
+  pub fn contains(&self, method: &str) -> bool {
+    self.handlers.contains_key(method)
+  }
+  pub fn methods(&self) -> Vec<&str> {
+    self.handlers.keys().map(|m| m.as_str()).collect()
+  }
+  // always answers a well formed request, unknown methods get ERROR_NOT_SUPPORTED
+  pub fn dispatch(&mut self, request: &Packet) -> Result<Packet, ProtocolError> {
+    let method = request.require_tlv(TlvType::Method)?.as_str()?;
+    let mut response = Packet::response_for(request)?;
+    let result = match self.handlers.get_mut(method) {
+      Some(handler) => handler.handle(request, &mut response),
+      None => Err(CommandError::new(ERROR_NOT_SUPPORTED, format!("{} is not supported", method))),
+    };
+    match result {
+      Ok(()) => Ok(response),
+      // anything the handler added before failing is dropped
+      Err(err) => Packet::error_response_for(request, err.code(), err.message()),
+    }
+  }
+}
@@ -0,0 +1,91 @@
+use alloc::vec::Vec;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use super::decoder::DEFAULT_MAX_PACKET_SIZE;
+use super::error::ProtocolError;
+use super::packet::*;
+use super::tlv::*;
+use super::utils::*;
+
+pub const COMPRESSION_LEVEL: u8 = 6;
+// compressed bodies start with the uncompressed length
+const COMPRESSED_LENGTH_SIZE: usize = 4;
+// the most a packet may inflate to, across all of its compressed tlvs
+pub const MAX_DECOMPRESSED_SIZE: usize = DEFAULT_MAX_PACKET_SIZE;
+
+impl Tlv {
+  pub fn is_compressed(&self) -> bool {
+    self.header().get_type().is_compressed()
+  }
+  // same tlv with the compressed modifier set and a zlib body, groups are
+  // left alone as meterpreter only compresses plain values
+  pub fn compress(&self) -> Result<Tlv, ProtocolError> {
+    if self.is_group() || self.is_compressed() {
+      return Ok(self.clone());
+    }
+    let ty = self.header().get_type();
+    let ty = ty.with_meta(ty.meta_type() | MetaType::COMPRESSED);
+    let mut buf: Vec<u8> = u32_to_vec_hton(self.buffer().len() as u32);
+    buf.append(&mut compress_to_vec_zlib(self.buffer(), COMPRESSION_LEVEL));
+    let header = TlvHeader::new().set_type(ty);
+    Ok(Tlv::new().set_header(header).set_buffer(buf))
+  }
+  pub fn decompress(&self) -> Result<Tlv, ProtocolError> {
+    if !self.is_compressed() {
+      return Ok(self.clone());
+    }
+    let (ty, buf) = self.inflate(MAX_DECOMPRESSED_SIZE)?;
+    let header = TlvHeader::new().set_type(ty);
+    Ok(Tlv::new().set_header(header).set_buffer(buf))
+  }
+  // the plain type and body of a compressed tlv; the peer picks the length,
+  // anything over maximum is refused up front
+  fn inflate(&self, maximum: usize) -> Result<(TlvType, Vec<u8>), ProtocolError> {
+    let val = self.buffer();
+    ProtocolError::check_len(val, COMPRESSED_LENGTH_SIZE)?;
+    let declared = slice_to_u32_ntoh(&val[..COMPRESSED_LENGTH_SIZE])?;
+    let length = declared as usize;
+    if length > maximum {
+      return Err(ProtocolError::PacketTooLarge { length: declared, maximum });
+    }
+    let buf = decompress_to_vec_zlib_with_limit(&val[COMPRESSED_LENGTH_SIZE..], length)
+      .map_err(|_| ProtocolError::DecompressionFailed)?;
+    if buf.len() != length {
+      return Err(ProtocolError::DecompressionFailed);
+    }
+    let ty = self.header().get_type();
+    Ok((ty.with_meta(ty.meta_type() & !MetaType::COMPRESSED), buf))
+  }
+}
+
+impl Packet {
+  // inflates every compressed tlv, nested ones too, into decompressed_buffers
+  // under its plain type; the payload keeps the wire form so the inflated
+  // bodies live only there. parsing calls this
+  pub fn decompress(&mut self) -> Result<(), ProtocolError> {
+    let mut buffers: Vec<DecompressedBuffer> = Vec::new();
+    let mut budget = MAX_DECOMPRESSED_SIZE;
+    if let Some(ref payload) = *self.payload() {
+      decompress_tlvs(payload, &mut buffers, &mut budget)?;
+    }
+    if !buffers.is_empty() {
+      self.set_decompressed_buffers_ref(buffers);
+    }
+    Ok(())
+  }
+}
+
+fn decompress_tlvs(tlvs: &[Tlv], buffers: &mut Vec<DecompressedBuffer>, budget: &mut usize) -> Result<(), ProtocolError> {
+  for tlv in tlvs.iter() {
+    if tlv.is_group() {
+      decompress_tlvs(tlv.children(), buffers, budget)?;
+    } else if tlv.is_compressed() {
+      let (ty, buf) = tlv.inflate(*budget)?;
+      *budget -= buf.len();
+      buffers.push(DecompressedBuffer::new(ty, buf));
+    }
+  }
+  Ok(())
+}
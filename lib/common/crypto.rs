@@ -0,0 +1,77 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use aes::Aes256;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use cbc::cipher::block_padding::Pkcs7;
+
+use super::error::ProtocolError;
+use super::packet::*;
+use super::tlv::{Tlv, TLV_HEADER_SIZE};
+use super::utils::Rng;
+
+pub const AES_KEY_SIZE: usize = 32;
+pub const AES_IV_SIZE: usize = 16;
+pub const AES_BLOCK_SIZE: usize = 16;
+pub type SymetricKey = [u8; AES_KEY_SIZE];
+
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+type Aes256CbcDec = cbc::Decryptor<Aes256>;
+
+// returns the iv followed by the pkcs7 padded ciphertext
+pub fn encrypt_aes256(key: &SymetricKey, iv: &[u8; AES_IV_SIZE], plain: &[u8]) -> Vec<u8> {
+  let mut vec: Vec<u8> = iv.to_vec();
+  let mut cipher = Aes256CbcEnc::new(key.into(), iv.into())
+    .encrypt_padded_vec_mut::<Pkcs7>(plain);
+  vec.append(&mut cipher);
+  vec
+}
+pub fn decrypt_aes256(key: &SymetricKey, val: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+  ProtocolError::check_len(val, AES_IV_SIZE + AES_BLOCK_SIZE)?;
+  let (iv, cipher) = val.split_at(AES_IV_SIZE);
+  if cipher.len() % AES_BLOCK_SIZE != 0 {
+    return Err(ProtocolError::DecryptionFailed);
+  }
+  Aes256CbcDec::new(key.into(), iv.into())
+    .decrypt_padded_vec_mut::<Pkcs7>(cipher)
+    .map_err(|_| ProtocolError::DecryptionFailed)
+}
+
+impl Packet {
+  // serializes with the tlv payload encrypted under key and a fresh iv
+  pub fn encode_encrypted<R>(self, key: &SymetricKey, rng: &mut R) -> Vec<u8>
+    where R: Rng
+  {
+    let mut iv = [0x00; AES_IV_SIZE];
+    rng.fill_bytes(&mut iv);
+    let header = self.header().set_enc_flags(ENC_FLAG_AES256);
+    let plain: Vec<u8> = self.into();
+    let body = encrypt_aes256(key, &iv, &plain[PACKET_HEADER_SIZE..]);
+
+    let header = header.set_length((TLV_HEADER_SIZE + body.len()) as u32);
+    let mut vec: Vec<u8> = header.into();
+    vec.extend(body);
+    vec
+  }
+  // accepts plaintext and aes256 packets, the result always holds plaintext
+  pub fn decode_encrypted(val: &[u8], key: &SymetricKey) -> Result<Packet, ProtocolError> {
+    let pkt = PacketRef::try_from(val)?;
+    match pkt.header().enc_flags() {
+      ENC_FLAG_NONE => pkt.to_packet(),
+      ENC_FLAG_AES256 => {
+        let plain = decrypt_aes256(key, pkt.payload())?;
+        let header = pkt.header()
+          .set_enc_flags(ENC_FLAG_NONE)
+          .set_length((TLV_HEADER_SIZE + plain.len()) as u32);
+        let packet = Packet::new()
+          .set_header(header)
+          .set_local(false);
+        if plain.is_empty() {
+          return Ok(packet);
+        }
+        Ok(packet.set_payload(Tlv::slice_to_tlv_vec(&plain)?))
+      },
+      flags => Err(ProtocolError::UnknownEncryption(flags)),
+    }
+  }
+}
@@ -0,0 +1,131 @@
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::codec::PacketCodec;
+use super::error::ProtocolError;
+use super::packet::*;
+use super::tlv::TLV_HEADER_SIZE;
+use super::utils::*;
+
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
+// reassembles packets from a byte stream that may split or coalesce them
+#[derive(Clone,Debug,Eq,PartialEq,Hash)]
+pub struct PacketDecoder {
+  buffer: Vec<u8>,
+  max_packet_size: usize,
+  // a bad length loses the frame boundaries for good, so the decoder stops
+  // here with its buffer dropped until it is reset
+  failed: Option<ProtocolError>,
+}
+impl Default for PacketDecoder {
+  fn default() -> Self {
+    PacketDecoder::new()
+  }
+}
+impl PacketDecoder {
+  pub fn new() -> Self {
+    PacketDecoder {
+      buffer: Vec::new(),
+      max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+      failed: None,
+    }
+  }
+  pub fn max_packet_size(&self) -> usize {
+    self.max_packet_size
+  }
+  pub fn set_max_packet_size(mut self, max: usize) -> Self {
+    self.set_max_packet_size_ref(max);
+    self
+  }
+  pub fn set_max_packet_size_ref(&mut self, max: usize) {
+    self.max_packet_size = max;
+  }
+  // bytes received but not yet returned as part of a packet
+  pub fn buffered(&self) -> usize {
+    self.buffer.len()
+  }
+  pub fn failed(&self) -> Option<&ProtocolError> {
+    self.failed.as_ref()
+  }
+  // forgets buffered bytes and any failure, for a fresh stream
+  pub fn reset(&mut self) {
+    self.buffer.clear();
+    self.failed = None;
+  }
+  // the limit is on declared frame lengths, a chunk may carry the end of one
+  // frame and the start of the next; drain complete frames between feeds,
+  // more is refused (without failing) once max_packet_size is buffered
+  pub fn feed(&mut self, chunk: &[u8]) -> Result<(), ProtocolError> {
+    if let Some(err) = self.failed {
+      return Err(err);
+    }
+    if self.buffer.len() > self.max_packet_size {
+      return Err(ProtocolError::PacketTooLarge {
+        length: saturate(self.buffer.len().saturating_add(chunk.len())),
+        maximum: self.max_packet_size,
+      });
+    }
+    self.buffer.extend_from_slice(chunk);
+    // a bad header is caught as soon as it arrives
+    self.frame_length().map_err(|err| self.fail(err))?;
+    Ok(())
+  }
+  fn fail(&mut self, err: ProtocolError) -> ProtocolError {
+    self.buffer = Vec::new();
+    self.failed = Some(err);
+    err
+  }
+  // length of the packet at the front of the buffer, once its header is in
+  fn frame_length(&self) -> Result<Option<usize>, ProtocolError> {
+    if self.buffer.len() < PACKET_HEADER_SIZE {
+      return Ok(None);
+    }
+    let mut key: XorKey = [0x00; XOR_KEY_SIZE];
+    key.copy_from_slice(&self.buffer[..XOR_KEY_SIZE]);
+    let mut length = [0x00; 4];
+    length.copy_from_slice(&self.buffer[PACKET_LENGTH_OFFSET..PACKET_LENGTH_OFFSET + 4]);
+    // the key repeats every four bytes and the field is aligned to it
+    xor_in_place(&key, &mut length);
+    let length = slice_to_u32_ntoh(&length)?;
+
+    if (length as usize) < TLV_HEADER_SIZE {
+      return Err(ProtocolError::LengthUnderflow {
+        length,
+        minimum: TLV_HEADER_SIZE,
+      });
+    }
+    match PACKET_LENGTH_OFFSET.checked_add(length as usize) {
+      Some(frame) if frame <= self.max_packet_size => Ok(Some(frame)),
+      frame => Err(ProtocolError::PacketTooLarge {
+        length: frame.map_or(u32::MAX, saturate),
+        maximum: self.max_packet_size,
+      }),
+    }
+  }
+  // the raw, still obfuscated bytes of the next complete packet
+  pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+    if let Some(err) = self.failed {
+      return Err(err);
+    }
+    let length = self.frame_length().map_err(|err| self.fail(err))?;
+    match length {
+      Some(length) if length <= self.buffer.len() => {
+        let rest = self.buffer.split_off(length);
+        Ok(Some(core::mem::replace(&mut self.buffer, rest)))
+      },
+      _ => Ok(None),
+    }
+  }
+  pub fn next_packet(&mut self, codec: &PacketCodec) -> Result<Option<Packet>, ProtocolError> {
+    match self.next_frame()? {
+      Some(frame) => codec.decode(&frame).map(Some),
+      None => Ok(None),
+    }
+  }
+}
+
+// sizes past u32 are reported as u32::MAX rather than wrapped
+fn saturate(len: usize) -> u32 {
+  u32::try_from(len).unwrap_or(u32::MAX)
+}
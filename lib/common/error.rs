@@ -0,0 +1,149 @@
+use core::fmt;
+
+use super::tlv::{MetaType, TlvPacketType, TlvType};
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub enum ProtocolError {
+  // fewer bytes were available than the structure requires
+  Truncated { needed: usize, got: usize },
+  // a length field points past the end of the available bytes
+  LengthOverflow { length: u32, available: usize },
+  // a length field is smaller than the header it describes
+  LengthUnderflow { length: u32, minimum: usize },
+  UnknownPacketType(u32),
+  NestedTooDeep { depth: usize },
+  // the tlv meta type does not hold the requested kind of value
+  TypeMismatch { expected: MetaType, actual: MetaType },
+  // string tlvs must be NUL terminated utf-8
+  InvalidString,
+  UnknownEncryption(u32),
+  // ciphertext was misaligned or its padding did not check out
+  DecryptionFailed,
+  MissingTlv(TlvType),
+  // a key offered by the peer could not be parsed or used
+  InvalidKey,
+  PacketTooLarge { length: u32, maximum: usize },
+  // a stored length disagrees with the data it describes
+  LengthMismatch { declared: u32, actual: u32 },
+  // a known packet type arrived somewhere it cannot be handled
+  UnexpectedPacketType(TlvPacketType),
+  InvalidUrl,
+  // a compressed tlv body was not valid zlib or not the size it claimed
+  DecompressionFailed,
+  // a request carried a method the handler it reached does not serve
+  UnexpectedMethod,
+  // a request id is already waiting on a response
+  DuplicateRequestId,
+  // a session guid that is not GUID_SIZE bytes
+  InvalidGuid { length: usize },
+}
+impl ProtocolError {
+  pub fn truncated(needed: usize, got: usize) -> Self {
+    ProtocolError::Truncated { needed, got }
+  }
+  // shorthand for the common "is this slice long enough" check
+  pub fn check_len(val: &[u8], needed: usize) -> Result<(), ProtocolError> {
+    if val.len() < needed {
+      return Err(ProtocolError::truncated(needed, val.len()));
+    }
+    Ok(())
+  }
+}
+impl fmt::Display for ProtocolError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ProtocolError::Truncated { needed, got } =>
+        write!(f, "truncated input: needed {} bytes, got {}", needed, got),
+      ProtocolError::LengthOverflow { length, available } =>
+        write!(f, "length {} exceeds the {} bytes available", length, available),
+      ProtocolError::LengthUnderflow { length, minimum } =>
+        write!(f, "length {} is smaller than the minimum of {}", length, minimum),
+      ProtocolError::UnknownPacketType(ty) =>
+        write!(f, "unknown packet type: {:#x}", ty),
+      ProtocolError::NestedTooDeep { depth } =>
+        write!(f, "tlv groups nested deeper than {}", depth),
+      ProtocolError::TypeMismatch { expected, actual } =>
+        write!(f, "expected meta type {:#x}, found {:#x}", expected.bits(), actual.bits()),
+      ProtocolError::InvalidString =>
+        write!(f, "string tlv is not NUL terminated utf-8"),
+      ProtocolError::UnknownEncryption(flags) =>
+        write!(f, "unknown encryption flags: {:#x}", flags),
+      ProtocolError::DecryptionFailed =>
+        write!(f, "payload failed to decrypt"),
+      ProtocolError::MissingTlv(ty) =>
+        write!(f, "missing {} tlv", ty.name()),
+      ProtocolError::InvalidKey =>
+        write!(f, "invalid key material"),
+      ProtocolError::PacketTooLarge { length, maximum } =>
+        write!(f, "packet length {} exceeds the maximum of {}", length, maximum),
+      ProtocolError::LengthMismatch { declared, actual } =>
+        write!(f, "declared length {} but the data is {} bytes", declared, actual),
+      ProtocolError::UnexpectedPacketType(ty) =>
+        write!(f, "unexpected {:?} packet", ty),
+      ProtocolError::InvalidUrl =>
+        write!(f, "invalid transport url"),
+      ProtocolError::DecompressionFailed =>
+        write!(f, "compressed tlv failed to decompress"),
+      ProtocolError::UnexpectedMethod =>
+        write!(f, "unexpected method"),
+      ProtocolError::DuplicateRequestId =>
+        write!(f, "request id is already in use"),
+      ProtocolError::InvalidGuid { length } =>
+        write!(f, "session guid is {} bytes", length),
+    }
+  }
+}
+impl core::error::Error for ProtocolError {}
+
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub enum TransportError {
+  Protocol(ProtocolError),
+  // the other end went away
+  Closed,
+  // the server answered with something other than 200
+  HttpStatus(u16),
+  RetriesExhausted,
+  SessionExpired,
+  // the tls client could not be set up
+  Tls,
+  #[cfg(feature = "std")]
+  Io(std::io::ErrorKind),
+}
+impl From<ProtocolError> for TransportError {
+  fn from(err: ProtocolError) -> Self {
+    TransportError::Protocol(err)
+  }
+}
+impl fmt::Display for TransportError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      TransportError::Protocol(ref err) => write!(f, "{}", err),
+      TransportError::Closed =>
+        write!(f, "transport closed"),
+      TransportError::HttpStatus(status) =>
+        write!(f, "http status {}", status),
+      TransportError::RetriesExhausted =>
+        write!(f, "gave up reconnecting the transport"),
+      TransportError::SessionExpired =>
+        write!(f, "session expired"),
+      TransportError::Tls =>
+        write!(f, "tls setup failed"),
+      #[cfg(feature = "std")]
+      TransportError::Io(kind) =>
+        write!(f, "transport io error: {}", kind),
+    }
+  }
+}
+impl core::error::Error for TransportError {}
+#[cfg(feature = "std")]
+impl From<std::io::Error> for TransportError {
+  fn from(err: std::io::Error) -> Self {
+    match err.kind() {
+      std::io::ErrorKind::UnexpectedEof
+        | std::io::ErrorKind::ConnectionReset
+        | std::io::ErrorKind::ConnectionAborted
+        | std::io::ErrorKind::BrokenPipe => TransportError::Closed,
+      kind => TransportError::Io(kind),
+    }
+  }
+}
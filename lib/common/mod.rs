@@ -1,16 +1,44 @@
 
+pub mod error;
 pub mod utils;
 pub mod tlv;
+pub mod result;
 pub mod packet;
+pub mod codec;
+pub mod decoder;
+pub mod command;
+pub mod channel;
+pub mod transport;
+pub mod session;
+pub mod supervisor;
+#[cfg(feature = "std")]
+pub mod tcp;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "crypto")]
+pub mod negotiate;
+#[cfg(feature = "compression")]
+pub mod compress;
 
 pub mod prelude {
+  pub use super::error::ProtocolError;
+  pub use super::error::TransportError;
+  pub use super::utils::Rng;
+
   pub use super::tlv::TlvPacketType;
   pub use super::tlv::TLV_PACKET_TYPE_SIZE;
+  pub use super::tlv::MetaType;
   pub use super::tlv::TlvType;
   pub use super::tlv::TLV_TYPE_SIZE;
   pub use super::tlv::TlvHeader;
   pub use super::tlv::TLV_HEADER_SIZE;
+  pub use super::tlv::TLV_MAX_DEPTH;
   pub use super::tlv::Tlv;
+  pub use super::tlv::TlvValue;
+  pub use super::tlv::TlvRef;
+  pub use super::tlv::TlvIter;
 
   pub use super::packet::XorKey;
   pub use super::packet::XOR_KEY_SIZE;
@@ -18,9 +46,56 @@ pub mod prelude {
   pub use super::packet::GUID_SIZE;
   pub use super::packet::PacketHeader;
   pub use super::packet::PACKET_HEADER_SIZE;
+  pub use super::packet::PACKET_LENGTH_OFFSET;
   pub use super::packet::Packet;
+  pub use super::packet::PacketRef;
   pub use super::packet::NULL_PACKET_SIZE;
   pub use super::packet::DecompressedBuffer;
+  pub use super::packet::ENC_FLAG_NONE;
+  pub use super::packet::ENC_FLAG_AES256;
+  pub use super::packet::Completion;
+  pub use super::packet::PacketRequestCompletion;
+  pub use super::packet::PacketCompletionRoutineEntry;
+  pub use super::packet::CompletionRegistry;
+
+  pub use super::codec::PacketCodec;
+  pub use super::codec::CodecState;
+  pub use super::decoder::PacketDecoder;
+  pub use super::command::CommandError;
+  pub use super::command::CommandHandler;
+  pub use super::command::CommandRegistry;
+  pub use super::channel::ChannelClass;
+  pub use super::channel::ChannelBackend;
+  pub use super::channel::ChannelFactory;
+  pub use super::channel::PoolBackend;
+  pub use super::channel::SeekFrom;
+  pub use super::channel::BufferedChannel;
+  pub use super::channel::MemoryFile;
+  pub use super::channel::Channel;
+  pub use super::channel::ChannelManager;
+  pub use super::transport::Transport;
+  pub use super::transport::TransportConfig;
+  pub use super::transport::TransportList;
+  pub use super::session::Received;
+  pub use super::session::Session;
+  pub use super::supervisor::Clock;
+  pub use super::supervisor::SupervisorAction;
+  pub use super::supervisor::TransportSupervisor;
+  #[cfg(feature = "std")]
+  pub use super::supervisor::SystemClock;
+  #[cfg(feature = "std")]
+  pub use super::tcp::TcpTransport;
+  #[cfg(feature = "http")]
+  pub use super::http::HttpConfig;
+  #[cfg(feature = "http")]
+  pub use super::http::HttpTransport;
+
+  #[cfg(feature = "crypto")]
+  pub use super::crypto::SymetricKey;
+  #[cfg(feature = "crypto")]
+  pub use super::crypto::AES_KEY_SIZE;
+  #[cfg(feature = "crypto")]
+  pub use super::negotiate::KeyNegotiation;
 }
 
 #[cfg(test)] mod test;
\ No newline at end of file
@@ -0,0 +1,142 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use rand_core::{CryptoRng, RngCore};
+use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use zeroize::Zeroize;
+
+use super::codec::*;
+use super::crypto::*;
+use super::error::ProtocolError;
+use super::packet::*;
+use super::tlv::*;
+use super::utils::Rng;
+
+pub const NEGOTIATE_METHOD: &str = "core_negotiate_tlv_encryption";
+
+#[derive(Clone,Eq,PartialEq,Hash)]
+pub enum NegotiationState {
+  Idle,
+  // key generated and answered, waiting for the response to go out
+  Pending(SymetricKey),
+  Complete,
+}
+impl fmt::Debug for NegotiationState {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      NegotiationState::Idle => write!(f, "Idle"),
+      NegotiationState::Pending(_) => write!(f, "Pending(<redacted>)"),
+      NegotiationState::Complete => write!(f, "Complete"),
+    }
+  }
+}
+impl Drop for NegotiationState {
+  fn drop(&mut self) {
+    if let NegotiationState::Pending(ref mut key) = *self {
+      key.zeroize();
+    }
+  }
+}
+
+// agent side of core_negotiate_tlv_encryption
+#[derive(Clone,Debug,Eq,PartialEq,Hash)]
+pub struct KeyNegotiation {
+  state: NegotiationState,
+}
+impl Default for KeyNegotiation {
+  fn default() -> Self {
+    KeyNegotiation::new()
+  }
+}
+impl KeyNegotiation {
+  pub fn new() -> Self {
+    KeyNegotiation {
+      state: NegotiationState::Idle,
+    }
+  }
+  pub fn state(&self) -> &NegotiationState {
+    &self.state
+  }
+  // builds the response to the server's request, wrapping a new session key
+  // with its rsa key when one is offered; rng must be cryptographically secure
+  pub fn handle_request<R>(&mut self, request: &Packet, rng: &mut R) -> Result<Packet, ProtocolError>
+    where R: Rng
+  {
+    match request.find_tlv(TlvType::Method) {
+      Some(method) if method.as_str()? == NEGOTIATE_METHOD => (),
+      Some(_) => return Err(ProtocolError::UnexpectedMethod),
+      None => return Err(ProtocolError::MissingTlv(TlvType::Method)),
+    }
+
+    let mut key: SymetricKey = [0x00; AES_KEY_SIZE];
+    rng.fill_bytes(&mut key);
+    let response = respond(request, &key, rng);
+    if response.is_ok() {
+      self.state = NegotiationState::Pending(key);
+    }
+    key.zeroize();
+    response
+  }
+  // the response itself goes out under the old state, only then switch over
+  pub fn response_sent(&mut self, codec: &mut PacketCodec) -> bool {
+    match self.state {
+      NegotiationState::Pending(ref key) => {
+        codec.set_state_ref(CodecState::Encrypted(*key));
+        self.state = NegotiationState::Complete;
+        true
+      },
+      _ => false,
+    }
+  }
+}
+
+fn respond<R>(request: &Packet, key: &SymetricKey, rng: &mut R) -> Result<Packet, ProtocolError>
+  where R: Rng
+{
+  let mut response = Packet::response_for(request)?;
+  response.add_tlv_ref(Tlv::uint(TlvType::SymetricKeyType, ENC_FLAG_AES256)?);
+
+  match request.find_tlv(TlvType::RsaPubKey) {
+    Some(pem) => {
+      let wrapped = wrap_key(pem.as_str()?, key, rng)?;
+      response.add_tlv_ref(Tlv::raw(TlvType::EncryptedSymetricKey, wrapped)?);
+    },
+    None => response.add_tlv_ref(Tlv::raw(TlvType::SymetricKey, key.to_vec())?),
+  }
+  Ok(response)
+}
+
+fn wrap_key<R>(pem: &str, key: &SymetricKey, rng: &mut R) -> Result<Vec<u8>, ProtocolError>
+  where R: Rng
+{
+  let public = RsaPublicKey::from_public_key_pem(pem)
+    .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+    .map_err(|_| ProtocolError::InvalidKey)?;
+  public.encrypt(&mut RngAdapter(rng), Pkcs1v15Encrypt, key)
+    .map_err(|_| ProtocolError::InvalidKey)
+}
+
+// lets a caller supplied Rng drive the rsa padding
+struct RngAdapter<'a, R: Rng>(&'a mut R);
+impl<'a, R: Rng> RngCore for RngAdapter<'a, R> {
+  fn next_u32(&mut self) -> u32 {
+    let mut buf = [0u8; 4];
+    self.0.fill_bytes(&mut buf);
+    u32::from_le_bytes(buf)
+  }
+  fn next_u64(&mut self) -> u64 {
+    let mut buf = [0u8; 8];
+    self.0.fill_bytes(&mut buf);
+    u64::from_le_bytes(buf)
+  }
+  fn fill_bytes(&mut self, dest: &mut [u8]) {
+    self.0.fill_bytes(dest);
+  }
+  fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+    self.0.fill_bytes(dest);
+    Ok(())
+  }
+}
+impl<'a, R: Rng> CryptoRng for RngAdapter<'a, R> {}
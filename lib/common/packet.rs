@@ -1,6 +1,14 @@
 use alloc::vec::Vec;
 use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::collections::BTreeMap;
+use core::convert::TryFrom;
+use core::fmt;
+use core::task::{Poll, Waker};
 
+use super::error::ProtocolError;
+use super::result::ERROR_SUCCESS;
 use super::tlv::*;
 use super::utils::*;
 
@@ -9,7 +17,35 @@ pub type XorKey = [u8; XOR_KEY_SIZE];
 pub const GUID_SIZE: usize = 16;
 pub type GuidBytes = [u8; GUID_SIZE];
 
+// xors buf with the repeating key, the same call obfuscates and restores
+pub fn xor_in_place(key: &XorKey, buf: &mut [u8]) {
+  for (i, b) in buf.iter_mut().enumerate() {
+    *b ^= key[i % XOR_KEY_SIZE];
+  }
+}
+// meterpreter keys never contain a zero byte
+pub fn random_xor_key<R>(rng: &mut R) -> XorKey
+  where R: Rng
+{
+  let mut key: XorKey = [0x00; XOR_KEY_SIZE];
+  rng.fill_bytes(&mut key);
+  for b in key.iter_mut() {
+    while *b == 0 {
+      let mut byte = [0u8; 1];
+      rng.fill_bytes(&mut byte);
+      *b = byte[0];
+    }
+  }
+  key
+}
+
+pub const ENC_FLAG_NONE: u32 = 0;
+pub const ENC_FLAG_AES256: u32 = 1;
+
 pub const PACKET_HEADER_SIZE: usize = XOR_KEY_SIZE + GUID_SIZE + 12;
+// where the length field sits; like a tlv length it counts itself, the type
+// and the payload, but not the xor key, guid and encryption flags before it
+pub const PACKET_LENGTH_OFFSET: usize = XOR_KEY_SIZE + GUID_SIZE + 4;
 #[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
 pub struct PacketHeader {
   key: XorKey,
@@ -18,13 +54,18 @@ pub struct PacketHeader {
   length: u32,
   type_: TlvPacketType,
 }
+impl Default for PacketHeader {
+  fn default() -> Self {
+    PacketHeader::new()
+  }
+}
 impl PacketHeader {
   pub fn new() -> PacketHeader {
     PacketHeader {
       key: [0x00; XOR_KEY_SIZE],
       session_guid: [0x00; GUID_SIZE],
       encryption_flags: 0,
-      length: PACKET_HEADER_SIZE as u32,
+      length: TLV_HEADER_SIZE as u32,
       type_: TlvPacketType::Request,
     }
   }
@@ -92,11 +133,10 @@ impl PacketHeader {
     self.type_ = ty.into();
   }
 }
-impl From<&[u8]> for PacketHeader {
-  fn from(val: &[u8]) -> PacketHeader {
-    if val.len() < PACKET_HEADER_SIZE {
-      panic!{"PacketHeader::From::<&[u8]> length < {}: {}", PACKET_HEADER_SIZE, val.len()};
-    }
+impl TryFrom<&[u8]> for PacketHeader {
+  type Error = ProtocolError;
+  fn try_from(val: &[u8]) -> Result<PacketHeader, ProtocolError> {
+    ProtocolError::check_len(val, PACKET_HEADER_SIZE)?;
     let (key_, val) = val.split_at(XOR_KEY_SIZE);
     let mut key: XorKey = [0; XOR_KEY_SIZE];
     key.copy_from_slice(key_);
@@ -104,37 +144,34 @@ impl From<&[u8]> for PacketHeader {
     let mut guid: GuidBytes = [0; GUID_SIZE];
     guid.copy_from_slice(guid_);
     let (flags, val) = val.split_at(4);
-    let flags: u32 = slice_to_u32_ntoh(flags);
+    let flags: u32 = slice_to_u32_ntoh(flags)?;
     let (length, val) = val.split_at(4);
-    let length: u32 = slice_to_u32_ntoh(length);
-    let type_: TlvPacketType = val.into();
+    let length: u32 = slice_to_u32_ntoh(length)?;
+    let type_: TlvPacketType = TlvPacketType::try_from(val)?;
 
-    PacketHeader {
-      key: key,
+    Ok(PacketHeader {
+      key,
       session_guid: guid,
       encryption_flags: flags,
-      length: length,
-      type_: type_,
-    }
+      length,
+      type_,
+    })
   }
 }
-impl From<Vec<u8>> for PacketHeader {
-  fn from(val: Vec<u8>) -> PacketHeader {
-    if val.len() < PACKET_HEADER_SIZE {
-      panic!{"PacketHeader::From::<Vec<u8>> length < {}: {}", PACKET_HEADER_SIZE, val.len()};
-    }
-    let val: &[u8] = &val;
-    val.into()
+impl TryFrom<Vec<u8>> for PacketHeader {
+  type Error = ProtocolError;
+  fn try_from(val: Vec<u8>) -> Result<PacketHeader, ProtocolError> {
+    PacketHeader::try_from(&val[..])
   }
 }
-impl Into<Vec<u8>> for PacketHeader {
-  fn into(self) -> Vec<u8> {
+impl From<PacketHeader> for Vec<u8> {
+  fn from(val: PacketHeader) -> Self {
     let mut header: Vec<u8> = Vec::with_capacity(PACKET_HEADER_SIZE);
-    header.append(&mut self.key.to_vec());
-    header.append(&mut self.session_guid.to_vec());
-    header.append(&mut u32_to_vec_hton(self.encryption_flags));
-    header.append(&mut u32_to_vec_hton(self.length));
-    header.append(&mut self.type_.into());
+    header.append(&mut val.key.to_vec());
+    header.append(&mut val.session_guid.to_vec());
+    header.append(&mut u32_to_vec_hton(val.encryption_flags));
+    header.append(&mut u32_to_vec_hton(val.length));
+    header.append(&mut val.type_.into());
     header
   }
 }
@@ -148,6 +185,11 @@ pub struct Packet {
   decompressed_buffers: Option<Vec<DecompressedBuffer>>,
   local: bool,
 }
+impl Default for Packet {
+  fn default() -> Self {
+    Packet::new()
+  }
+}
 impl Packet {
   pub fn new() -> Packet {
     Packet {
@@ -167,12 +209,18 @@ impl Packet {
     self.set_header_ref(header);
     self
   }
+  // the length is always recomputed from the payload
   pub fn set_header_ref(&mut self, header: PacketHeader) {
     self.header = header;
+    self.refresh_length();
   }
   pub fn payload(&self) -> &Option<Vec<Tlv>> {
     &self.payload
   }
+  // call sync_length after editing through here
+  pub fn mut_payload(&mut self) -> &mut Option<Vec<Tlv>> {
+    &mut self.payload
+  }
   pub fn set_payload<V>(mut self, payload: V) -> Self
     where V: Into<Vec<Tlv>>
   {
@@ -184,18 +232,61 @@ impl Packet {
   {
     let buf: Vec<Tlv> = payload.into();
     self.payload = Some(buf);
+    self.refresh_length();
   }
   pub fn payload_length(&self) -> u32 {
     match self.payload {
       None => 0,
       Some(ref p) => {
         p.iter().fold(0u32, |mut sum, tlv| {
-          sum+=tlv.header().length();
+          sum+=tlv.encoded_length();
           sum
         })
       }
     }
   }
+  // full length on the wire, header included
+  pub fn length(&self) -> u32 {
+    PACKET_HEADER_SIZE as u32 + self.payload_length()
+  }
+  // what goes in the header's length field
+  pub fn header_length(&self) -> u32 {
+    TLV_HEADER_SIZE as u32 + self.payload_length()
+  }
+  // only needed after editing through mut_header or the tlvs' mut_ accessors,
+  // serialization always writes the computed lengths
+  pub fn sync_length(&mut self) {
+    if let Some(ref mut p) = self.payload {
+      for tlv in p.iter_mut() {
+        tlv.sync_length();
+      }
+    }
+    let length = self.header_length();
+    self.header.set_length_ref(length);
+  }
+  // checks every stored length against what it describes, useful on packets
+  // that were parsed or edited through the mut_ accessors; the innermost
+  // stale length is the one reported
+  pub fn validate(&self) -> Result<(), ProtocolError> {
+    if let Some(ref p) = self.payload {
+      p.iter().try_for_each(Tlv::validate)?;
+    }
+    if self.header.length() != self.header_length() {
+      return Err(ProtocolError::LengthMismatch {
+        declared: self.header.length(),
+        actual: self.header_length(),
+      });
+    }
+    Ok(())
+  }
+  // header length from the tlvs' stored lengths, without walking into them
+  fn refresh_length(&mut self) {
+    let length = match self.payload {
+      None => TLV_HEADER_SIZE as u32,
+      Some(ref p) => p.iter().fold(TLV_HEADER_SIZE as u32, |sum, tlv| sum + tlv.header().length()),
+    };
+    self.header.set_length_ref(length);
+  }
   pub fn decompressed_buffers(&self) -> &Option<Vec<DecompressedBuffer>> {
     &self.decompressed_buffers
   }
@@ -225,6 +316,7 @@ impl Packet {
     self
   }
   pub fn add_tlv_ref(&mut self, tlv: Tlv) {
+    let tlv_length = tlv.header().length();
     if let Some(ref mut p) = self.payload {
       p.push(tlv);
     } else {
@@ -232,6 +324,86 @@ impl Packet {
       vec.push(tlv);
       self.payload = Some(vec);
     }
+    let length = self.header.length() + tlv_length;
+    self.header.set_length_ref(length);
+  }
+  // serializes with key stored in the header and applied to everything after it
+  pub fn encode_xor(mut self, key: XorKey) -> Vec<u8> {
+    self.header.set_key_ref(key);
+    let mut vec: Vec<u8> = self.into();
+    xor_in_place(&key, &mut vec[XOR_KEY_SIZE..]);
+    vec
+  }
+  pub fn encode_xor_rng<R>(self, rng: &mut R) -> Vec<u8>
+    where R: Rng
+  {
+    let key = random_xor_key(rng);
+    self.encode_xor(key)
+  }
+  pub fn decode_xor(val: &[u8]) -> Result<Packet, ProtocolError> {
+    ProtocolError::check_len(val, XOR_KEY_SIZE)?;
+    let mut key: XorKey = [0x00; XOR_KEY_SIZE];
+    key.copy_from_slice(&val[..XOR_KEY_SIZE]);
+    let mut vec: Vec<u8> = val.to_vec();
+    xor_in_place(&key, &mut vec[XOR_KEY_SIZE..]);
+    Packet::try_from(&vec[..])
+  }
+  pub fn find_tlv(&self, ty: TlvType) -> Option<&Tlv> {
+    self.payload.as_ref()?
+      .iter()
+      .find(|tlv| tlv.header().get_type() == ty)
+  }
+  // what a compressed tlv inflated to on parse, by its plain type
+  pub fn find_decompressed(&self, ty: TlvType) -> Option<&DecompressedBuffer> {
+    self.decompressed_buffers.as_ref()?
+      .iter()
+      .find(|buffer| buffer.tlv_type() == ty)
+  }
+  // the body of a raw tlv, whether it arrived plain or compressed
+  pub fn find_bytes(&self, ty: TlvType) -> Option<&[u8]> {
+    match self.find_tlv(ty) {
+      Some(tlv) => tlv.as_bytes().ok(),
+      None => self.find_decompressed(ty).map(|buffer| &buffer.buffer()[..]),
+    }
+  }
+  // like find_tlv, but for arguments a handler cannot do without
+  pub fn require_tlv(&self, ty: TlvType) -> Result<&Tlv, ProtocolError> {
+    self.find_tlv(ty).ok_or(ProtocolError::MissingTlv(ty))
+  }
+  pub fn request_id(&self) -> Option<&str> {
+    self.find_tlv(TlvType::RequestId)?.as_str().ok()
+  }
+  // starts a successful reply carrying the request's guid, Method and RequestId
+  pub fn response_for(request: &Packet) -> Result<Packet, ProtocolError> {
+    let mut response = Packet::reply_to(request)?;
+    response.add_tlv_ref(Tlv::uint(TlvType::Result, ERROR_SUCCESS)?);
+    Ok(response)
+  }
+  pub fn error_response_for(request: &Packet, code: u32, message: &str) -> Result<Packet, ProtocolError> {
+    let exception = Tlv::group(TlvType::Exception)?
+      .add_child(Tlv::uint(TlvType::ExceptionCode, code)?)
+      .add_child(Tlv::string(TlvType::ExceptionString, message)?);
+    let response = Packet::reply_to(request)?
+      .add_tlv(Tlv::uint(TlvType::Result, code)?)
+      .add_tlv(exception);
+    Ok(response)
+  }
+  fn reply_to(request: &Packet) -> Result<Packet, ProtocolError> {
+    let reply_type = match *request.header().get_type() {
+      TlvPacketType::Request => TlvPacketType::Response,
+      TlvPacketType::PlainRequest => TlvPacketType::PlainResponse,
+      ty => return Err(ProtocolError::UnexpectedPacketType(ty)),
+    };
+    let header = PacketHeader::new()
+      .set_guid(*request.header().guid())
+      .set_type(reply_type);
+    let mut response = Packet::new().set_header(header);
+    for ty in [TlvType::Method, TlvType::RequestId].iter() {
+      if let Some(tlv) = request.find_tlv(*ty) {
+        response.add_tlv_ref(tlv.clone());
+      }
+    }
+    Ok(response)
   }
   pub fn create<T>(pkt_type: TlvPacketType, tlv: T) -> Packet
     where T: Into<Tlv>
@@ -243,63 +415,307 @@ impl Packet {
       .add_tlv(tlv.into())
   }
 }
-impl From<&[u8]> for Packet {
-  fn from(val: &[u8]) -> Packet {
-    if val.len() < PACKET_HEADER_SIZE {
-      panic!{"Packet::From::<&[u8]> length < {} : {}", PACKET_HEADER_SIZE, val.len()};
+impl TryFrom<&[u8]> for Packet {
+  type Error = ProtocolError;
+  fn try_from(val: &[u8]) -> Result<Packet, ProtocolError> {
+    PacketRef::try_from(val)?.to_packet()
+  }
+}
+impl TryFrom<Vec<u8>> for Packet {
+  type Error = ProtocolError;
+  fn try_from(val: Vec<u8>) -> Result<Packet, ProtocolError> {
+    Packet::try_from(&val[..])
+  }
+}
+impl From<Packet> for Vec<u8> {
+  fn from(val: Packet) -> Self {
+    let mut payload: Vec<u8> = Vec::new();
+    if let Some(pay) = val.payload {
+      for tlv in pay.into_iter() {
+        let mut tlv: Vec<u8> = tlv.into();
+        payload.append(&mut tlv);
+      }
     }
-    let (header, val) = val.split_at(PACKET_HEADER_SIZE);
-    let header: PacketHeader = header.into();
+    let header = val.header.set_length((TLV_HEADER_SIZE + payload.len()) as u32);
+    let mut vec: Vec<u8> = Vec::with_capacity(PACKET_HEADER_SIZE + payload.len());
+    vec.append(&mut header.into());
+    vec.append(&mut payload);
+    vec
+  }
+}
+
+// borrowed view of a packet, tlvs are only decoded when walked
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct PacketRef<'a> {
+  header: PacketHeader,
+  payload: &'a [u8],
+}
+impl<'a> PacketRef<'a> {
+  pub fn header(&self) -> &PacketHeader {
+    &self.header
+  }
+  pub fn payload(&self) -> &'a [u8] {
+    self.payload
+  }
+  pub fn tlvs(&self) -> TlvIter<'a> {
+    TlvIter::new(self.payload)
+  }
+  pub fn find(&self, ty: TlvType) -> Option<TlvRef<'a>> {
+    self.tlvs()
+      .filter_map(Result::ok)
+      .find(|tlv| tlv.header().get_type() == ty)
+  }
+  pub fn to_packet(&self) -> Result<Packet, ProtocolError> {
     let mut payload: Option<Vec<Tlv>> = None;
-    if header.length() - PACKET_HEADER_SIZE as u32 != 0 {
-      let (payload_, val) = val.split_at(header.length() as usize - PACKET_HEADER_SIZE);
-      payload = Tlv::slice_to_tlv_vec(payload_);
+    if !self.payload.is_empty() {
+      payload = Some(Tlv::slice_to_tlv_vec(self.payload)?);
     }
 
-    Packet {
-      header: header,
-      payload: payload,
+    let packet = Packet {
+      header: self.header,
+      payload,
       decompressed_buffers: None,
       local: false,
-    }
+    };
+    #[cfg(feature = "compression")]
+    let packet = {
+      let mut packet = packet;
+      packet.decompress()?;
+      packet
+    };
+    Ok(packet)
   }
 }
-impl From<Vec<u8>> for Packet {
-  fn from(val: Vec<u8>) -> Packet {
-    if val.len() < PACKET_HEADER_SIZE {
-      panic!{"Packet::From::<Vec<u8>> length < {} : {}", PACKET_HEADER_SIZE, val.len()};
+impl<'a> TryFrom<&'a [u8]> for PacketRef<'a> {
+  type Error = ProtocolError;
+  fn try_from(val: &'a [u8]) -> Result<PacketRef<'a>, ProtocolError> {
+    ProtocolError::check_len(val, PACKET_HEADER_SIZE)?;
+    let (header, val) = val.split_at(PACKET_HEADER_SIZE);
+    let header: PacketHeader = PacketHeader::try_from(header)?;
+    if (header.length() as usize) < TLV_HEADER_SIZE {
+      return Err(ProtocolError::LengthUnderflow {
+        length: header.length(),
+        minimum: TLV_HEADER_SIZE,
+      });
+    }
+    let payload_len = header.length() as usize - TLV_HEADER_SIZE;
+    if val.len() < payload_len {
+      return Err(ProtocolError::LengthOverflow {
+        length: header.length(),
+        available: val.len() + TLV_HEADER_SIZE,
+      });
+    }
+    // framing is the decoder's job, anything past the packet is an error
+    if val.len() > payload_len {
+      return Err(ProtocolError::LengthMismatch {
+        declared: header.length(),
+        actual: (val.len() + TLV_HEADER_SIZE) as u32,
+      });
     }
-    let val: &[u8] = &val;
-    val.into()
+
+    Ok(PacketRef {
+      header,
+      payload: &val[..payload_len],
+    })
   }
 }
-impl Into<Vec<u8>> for Packet {
-  fn into(self) -> Vec<u8> {
-    let mut vec: Vec<u8> = Vec::with_capacity(self.header.length() as usize);
-    vec.append(&mut self.header.into());
-    if let Some(pay) = self.payload {
-      for tlv in pay.into_iter() {
-        let mut tlv: Vec<u8> = tlv.into();
-        vec.append(&mut tlv);
-      }
-    }
-    vec
+impl<'a> TryFrom<PacketRef<'a>> for Packet {
+  type Error = ProtocolError;
+  fn try_from(val: PacketRef<'a>) -> Result<Packet, ProtocolError> {
+    val.to_packet()
   }
 }
 
+// a tlv body that arrived compressed, as inflated on parse
 #[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
 pub struct DecompressedBuffer {
+  ty: TlvType,
   buffer: Vec<u8>,
   length: u32,
 }
+impl DecompressedBuffer {
+  pub fn new(ty: TlvType, buffer: Vec<u8>) -> Self {
+    DecompressedBuffer {
+      ty,
+      length: buffer.len() as u32,
+      buffer,
+    }
+  }
+  pub fn tlv_type(&self) -> TlvType {
+    self.ty
+  }
+  pub fn buffer(&self) -> &Vec<u8> {
+    &self.buffer
+  }
+  pub fn length(&self) -> u32 {
+    self.length
+  }
+}
 
-#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
-pub struct PacketRequestCompletion {
+// how a request finishes, either its response arrived or its deadline passed
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Completion {
+  Response(Packet),
+  TimedOut,
+}
 
+// what to do once a request completes
+pub enum PacketRequestCompletion {
+  Callback(Box<dyn FnOnce(Completion)>),
+  // the completion is parked in the registry until polled
+  Waker(Option<Waker>),
+}
+impl PacketRequestCompletion {
+  pub fn callback<F>(f: F) -> Self
+    where F: FnOnce(Completion) + 'static
+  {
+    PacketRequestCompletion::Callback(Box::new(f))
+  }
+  pub fn waker() -> Self {
+    PacketRequestCompletion::Waker(None)
+  }
+}
+impl fmt::Debug for PacketRequestCompletion {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      PacketRequestCompletion::Callback(_) => write!(f, "Callback"),
+      PacketRequestCompletion::Waker(ref w) => write!(f, "Waker({:?})", w),
+    }
+  }
 }
 
-#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+#[derive(Debug)]
 pub struct PacketCompletionRoutineEntry {
   request_id: String,
   handler: PacketRequestCompletion,
-}
\ No newline at end of file
+  // in whatever monotonic units the caller passes to expire
+  deadline: Option<u64>,
+}
+impl PacketCompletionRoutineEntry {
+  pub fn new(request_id: String, handler: PacketRequestCompletion, deadline: Option<u64>) -> Self {
+    PacketCompletionRoutineEntry {
+      request_id,
+      handler,
+      deadline,
+    }
+  }
+  pub fn request_id(&self) -> &str {
+    &self.request_id
+  }
+  pub fn deadline(&self) -> Option<u64> {
+    self.deadline
+  }
+  fn complete(self, completion: Completion, ready: &mut BTreeMap<String, Completion>) {
+    match self.handler {
+      PacketRequestCompletion::Callback(f) => f(completion),
+      PacketRequestCompletion::Waker(waker) => {
+        ready.insert(self.request_id, completion);
+        if let Some(waker) = waker {
+          waker.wake();
+        }
+      }
+    }
+  }
+}
+
+// correlates outgoing requests with their responses by RequestId
+#[derive(Debug)]
+pub struct CompletionRegistry {
+  next_id: u64,
+  pending: BTreeMap<String, PacketCompletionRoutineEntry>,
+  ready: BTreeMap<String, Completion>,
+}
+impl Default for CompletionRegistry {
+  fn default() -> Self {
+    CompletionRegistry::new()
+  }
+}
+impl CompletionRegistry {
+  pub fn new() -> Self {
+    CompletionRegistry {
+      next_id: 1,
+      pending: BTreeMap::new(),
+      ready: BTreeMap::new(),
+    }
+  }
+  pub fn next_request_id(&mut self) -> String {
+    let id = format!("{:016}", self.next_id);
+    self.next_id+=1;
+    id
+  }
+  // adds a RequestId to the request unless it already carries one
+  pub fn stamp(&mut self, request: &mut Packet) -> Result<String, ProtocolError> {
+    if let Some(id) = request.request_id() {
+      return Ok(String::from(id));
+    }
+    let id = self.next_request_id();
+    request.add_tlv_ref(Tlv::string(TlvType::RequestId, &id)?);
+    Ok(id)
+  }
+  // an id that is still pending or completed but not yet polled is refused
+  pub fn register(&mut self, request: &mut Packet, handler: PacketRequestCompletion, deadline: Option<u64>)
+    -> Result<String, ProtocolError>
+  {
+    if let Some(id) = request.request_id() {
+      if self.pending.contains_key(id) || self.ready.contains_key(id) {
+        return Err(ProtocolError::DuplicateRequestId);
+      }
+    }
+    let id = self.stamp(request)?;
+    let entry = PacketCompletionRoutineEntry::new(id.clone(), handler, deadline);
+    self.pending.insert(id.clone(), entry);
+    Ok(id)
+  }
+  pub fn pending(&self) -> usize {
+    self.pending.len()
+  }
+  pub fn is_pending(&self, request_id: &str) -> bool {
+    self.pending.contains_key(request_id)
+  }
+  // drops a waiter without completing it
+  pub fn cancel(&mut self, request_id: &str) -> bool {
+    self.ready.remove(request_id);
+    self.pending.remove(request_id).is_some()
+  }
+  // hands a response to its waiter, anything that is not an awaited
+  // response is given back to the caller
+  pub fn dispatch(&mut self, packet: Packet) -> Option<Packet> {
+    match *packet.header().get_type() {
+      TlvPacketType::Response | TlvPacketType::PlainResponse => {},
+      _ => return Some(packet),
+    }
+    let entry = match packet.request_id().and_then(|id| self.pending.remove(id)) {
+      Some(entry) => entry,
+      None => return Some(packet),
+    };
+    entry.complete(Completion::Response(packet), &mut self.ready);
+    None
+  }
+  // times out every waiter whose deadline is at or before now
+  pub fn expire(&mut self, now: u64) -> usize {
+    let expired: Vec<String> = self.pending.values()
+      .filter(|entry| entry.deadline.is_some_and(|d| d <= now))
+      .map(|entry| entry.request_id.clone())
+      .collect();
+    for id in expired.iter() {
+      if let Some(entry) = self.pending.remove(id) {
+        entry.complete(Completion::TimedOut, &mut self.ready);
+      }
+    }
+    expired.len()
+  }
+  // for waker handlers; Ready(None) means nothing will ever complete here,
+  // either the id is not known or its completion goes to a callback
+  pub fn poll(&mut self, request_id: &str, waker: &Waker) -> Poll<Option<Completion>> {
+    if let Some(completion) = self.ready.remove(request_id) {
+      return Poll::Ready(Some(completion));
+    }
+    match self.pending.get_mut(request_id).map(|entry| &mut entry.handler) {
+      Some(PacketRequestCompletion::Waker(ref mut w)) => {
+        *w = Some(waker.clone());
+        Poll::Pending
+      },
+      _ => Poll::Ready(None),
+    }
+  }
+}
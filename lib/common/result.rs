@@ -0,0 +1,6 @@
+// windows error codes, which is what the other end expects in Result tlvs
+pub const ERROR_SUCCESS: u32 = 0;
+pub const ERROR_NOT_ENOUGH_MEMORY: u32 = 8;
+pub const ERROR_NOT_SUPPORTED: u32 = 50;
+pub const ERROR_INVALID_PARAMETER: u32 = 87;
+pub const ERROR_NOT_FOUND: u32 = 1168;
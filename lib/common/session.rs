@@ -0,0 +1,205 @@
+use alloc::string::String;
+
+use super::channel::ChannelManager;
+use super::codec::*;
+use super::command::CommandRegistry;
+use super::error::{ProtocolError, TransportError};
+use super::packet::*;
+use super::tlv::*;
+use super::transport::{Transport, TransportList};
+use super::utils::Rng;
+
+// what a single recv call came back with
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Received {
+  // the transport had nothing
+  Idle,
+  // a response went to the waiter registered for it
+  Completed,
+  // a response to a channel write reopened that channel's window
+  Acknowledged,
+  // anything the session does not handle itself
+  Packet(Packet),
+}
+
+// everything that outlives a single packet: who we are, how packets are
+// encoded, which requests are still waiting and where packets go
+#[derive(Debug)]
+pub struct Session<T> {
+  guid: GuidBytes,
+  codec: PacketCodec,
+  completions: CompletionRegistry,
+  transport: T,
+  transports: TransportList,
+  channels: ChannelManager,
+}
+impl<T: Transport> Session<T> {
+  // starts with the null guid until one is generated or the server assigns one
+  pub fn new(transport: T) -> Self {
+    Session {
+      guid: [0x00; GUID_SIZE],
+      codec: PacketCodec::new(),
+      completions: CompletionRegistry::new(),
+      transport,
+      transports: TransportList::new(),
+      channels: ChannelManager::new(),
+    }
+  }
+  pub fn guid(&self) -> &GuidBytes {
+    &self.guid
+  }
+  pub fn set_guid(mut self, guid: GuidBytes) -> Self {
+    self.set_guid_ref(guid);
+    self
+  }
+  pub fn set_guid_ref(&mut self, guid: GuidBytes) {
+    self.guid = guid;
+  }
+  pub fn generate_guid<R>(mut self, rng: &mut R) -> Self
+    where R: Rng
+  {
+    self.generate_guid_ref(rng);
+    self
+  }
+  pub fn generate_guid_ref<R>(&mut self, rng: &mut R)
+    where R: Rng
+  {
+    rng.fill_bytes(&mut self.guid);
+  }
+  // adopts the guid from a SessionGuid tlv, false when the packet has none
+  pub fn assign_guid(&mut self, packet: &Packet) -> Result<bool, ProtocolError> {
+    let tlv = match packet.find_tlv(TlvType::SessionGuid) {
+      Some(tlv) => tlv,
+      None => return Ok(false),
+    };
+    let bytes = tlv.as_bytes()?;
+    if bytes.len() != GUID_SIZE {
+      return Err(ProtocolError::InvalidGuid { length: bytes.len() });
+    }
+    self.guid.copy_from_slice(bytes);
+    Ok(true)
+  }
+  pub fn codec(&self) -> &PacketCodec {
+    &self.codec
+  }
+  pub fn mut_codec(&mut self) -> &mut PacketCodec {
+    &mut self.codec
+  }
+  pub fn completions(&self) -> &CompletionRegistry {
+    &self.completions
+  }
+  pub fn mut_completions(&mut self) -> &mut CompletionRegistry {
+    &mut self.completions
+  }
+  pub fn transport(&self) -> &T {
+    &self.transport
+  }
+  pub fn mut_transport(&mut self) -> &mut T {
+    &mut self.transport
+  }
+  // swaps in a newly connected transport, handing back the old one
+  pub fn replace_transport(&mut self, transport: T) -> T {
+    core::mem::replace(&mut self.transport, transport)
+  }
+  pub fn transports(&self) -> &TransportList {
+    &self.transports
+  }
+  pub fn set_transports(mut self, transports: TransportList) -> Self {
+    self.set_transports_ref(transports);
+    self
+  }
+  pub fn set_transports_ref(&mut self, transports: TransportList) {
+    self.transports = transports;
+  }
+  pub fn mut_transports(&mut self) -> &mut TransportList {
+    &mut self.transports
+  }
+  pub fn channels(&self) -> &ChannelManager {
+    &self.channels
+  }
+  pub fn set_channels(mut self, channels: ChannelManager) -> Self {
+    self.set_channels_ref(channels);
+    self
+  }
+  pub fn set_channels_ref(&mut self, channels: ChannelManager) {
+    self.channels = channels;
+  }
+  pub fn mut_channels(&mut self) -> &mut ChannelManager {
+    &mut self.channels
+  }
+  // answers a request, the session's own commands first and then commands
+  pub fn dispatch(&mut self, request: &Packet, commands: &mut CommandRegistry) -> Result<Packet, ProtocolError> {
+    let mut response = Packet::response_for(request)?;
+    let handled = match self.transports.handle(request, &mut response) {
+      None => self.channels.handle(request, &mut response),
+      handled => handled,
+    };
+    match handled {
+      Some(Ok(())) => Ok(response),
+      Some(Err(err)) => Packet::error_response_for(request, err.code(), err.message()),
+      None => commands.dispatch(request),
+    }
+  }
+  // puts our guid on the packet and a RequestId on requests that lack one
+  pub fn stamp(&mut self, packet: &mut Packet) -> Result<(), ProtocolError> {
+    packet.mut_header().set_guid_ref(self.guid);
+    match *packet.header().get_type() {
+      TlvPacketType::Request | TlvPacketType::PlainRequest => {
+        self.completions.stamp(packet)?;
+      },
+      _ => {},
+    }
+    Ok(())
+  }
+  pub fn send<R>(&mut self, mut packet: Packet, rng: &mut R) -> Result<(), TransportError>
+    where R: Rng
+  {
+    self.stamp(&mut packet)?;
+    let frame = self.codec.encode(packet, rng);
+    self.transport.send_frame(&frame)
+  }
+  // sends a request whose response goes to handler, returning its RequestId
+  pub fn request<R>(&mut self, mut packet: Packet, handler: PacketRequestCompletion, deadline: Option<u64>, rng: &mut R)
+    -> Result<String, TransportError>
+    where R: Rng
+  {
+    let id = self.completions.register(&mut packet, handler, deadline)?;
+    if let Err(err) = self.send(packet, rng) {
+      self.completions.cancel(&id);
+      return Err(err);
+    }
+    Ok(id)
+  }
+  // responses are handed to their waiters, or used to reopen channel
+  // windows, everything else is returned
+  pub fn recv(&mut self) -> Result<Received, TransportError> {
+    let frame = match self.transport.recv_frame()? {
+      Some(frame) => frame,
+      None => return Ok(Received::Idle),
+    };
+    let packet = self.codec.decode(&frame)?;
+    if self.channels.acknowledge(&packet) {
+      return Ok(Received::Acknowledged);
+    }
+    match self.completions.dispatch(packet) {
+      Some(packet) => Ok(Received::Packet(packet)),
+      None => Ok(Received::Completed),
+    }
+  }
+  // sends whatever interactive channels have produced, as far as their
+  // windows allow; returns the number of writes sent
+  pub fn flush_channels<R>(&mut self, rng: &mut R) -> Result<usize, TransportError>
+    where R: Rng
+  {
+    self.channels.pump();
+    let mut sent = 0;
+    while let Some(mut packet) = self.channels.next_outbound() {
+      self.stamp(&mut packet)?;
+      // a failed send leaves the data queued for the next flush
+      self.send(packet.clone(), rng)?;
+      self.channels.track(&packet)?;
+      sent += 1;
+    }
+    Ok(sent)
+  }
+}
@@ -0,0 +1,159 @@
+use core::cmp;
+
+use super::error::TransportError;
+use super::transport::*;
+
+// what the transport owner should do next
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub enum SupervisorAction {
+  Continue,
+  // (re)connect the transport now
+  Reconnect,
+  // nothing to do for this many seconds
+  Wait(u64),
+  // the retry budget is spent, try another transport or stop
+  GiveUp,
+  // the session has outlived its expiration and should be torn down
+  Expire,
+}
+
+// seconds from any fixed point, plus a way to pass them
+pub trait Clock {
+  fn now(&self) -> u64;
+  fn sleep(&mut self, secs: u64);
+}
+
+#[cfg(feature = "std")]
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct SystemClock {
+  start: std::time::Instant,
+}
+#[cfg(feature = "std")]
+impl SystemClock {
+  pub fn new() -> Self {
+    SystemClock {
+      start: std::time::Instant::now(),
+    }
+  }
+}
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+  fn default() -> Self {
+    SystemClock::new()
+  }
+}
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+  fn now(&self) -> u64 {
+    self.start.elapsed().as_secs()
+  }
+  fn sleep(&mut self, secs: u64) {
+    std::thread::sleep(std::time::Duration::from_secs(secs));
+  }
+}
+
+// tracks the comm timeout, retry budget and session expiration of the
+// active transport; all times are seconds from the caller's clock
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct TransportSupervisor {
+  comm_timeout: u32,
+  retry_total: u32,
+  retry_wait: u32,
+  expires_at: u64,
+  last_contact: u64,
+  failing_since: Option<u64>,
+  next_attempt: u64,
+}
+impl TransportSupervisor {
+  pub fn new(config: &TransportConfig, now: u64) -> Self {
+    let expiration = config.session_expiration().unwrap_or(DEFAULT_SESSION_EXPIRATION);
+    TransportSupervisor {
+      comm_timeout: config.comm_timeout(),
+      retry_total: config.retry_total(),
+      retry_wait: config.retry_wait(),
+      expires_at: now + expiration as u64,
+      last_contact: now,
+      failing_since: None,
+      next_attempt: now,
+    }
+  }
+  // moves to another transport's settings with a fresh retry budget, the
+  // session expiration only changes when the new config carries one
+  pub fn set_transport(&mut self, config: &TransportConfig, now: u64) {
+    self.comm_timeout = config.comm_timeout();
+    self.retry_total = config.retry_total();
+    self.retry_wait = config.retry_wait();
+    if let Some(expiration) = config.session_expiration() {
+      self.expires_at = now + expiration as u64;
+    }
+    self.last_contact = now;
+    self.failing_since = None;
+    self.next_attempt = now;
+  }
+  pub fn expires_at(&self) -> u64 {
+    self.expires_at
+  }
+  pub fn last_contact(&self) -> u64 {
+    self.last_contact
+  }
+  pub fn is_failing(&self) -> bool {
+    self.failing_since.is_some()
+  }
+  // any successful traffic, which also ends a run of failures
+  pub fn contact(&mut self, now: u64) {
+    self.last_contact = now;
+    self.failing_since = None;
+  }
+  pub fn failure(&mut self, now: u64) {
+    if self.failing_since.is_none() {
+      self.failing_since = Some(now);
+      self.next_attempt = now + self.wait();
+    }
+  }
+  pub fn poll(&mut self, now: u64) -> SupervisorAction {
+    if now >= self.expires_at {
+      return SupervisorAction::Expire;
+    }
+    // a silent transport counts as failed, and is retried straight away
+    if self.failing_since.is_none() && now >= self.last_contact + self.comm_timeout as u64 {
+      self.failing_since = Some(now);
+      self.next_attempt = now;
+    }
+    let since = match self.failing_since {
+      Some(since) => since,
+      None => return SupervisorAction::Continue,
+    };
+    if now >= since + self.retry_total as u64 {
+      return SupervisorAction::GiveUp;
+    }
+    if now >= self.next_attempt {
+      self.next_attempt = now + self.wait();
+      return SupervisorAction::Reconnect;
+    }
+    SupervisorAction::Wait(cmp::min(self.next_attempt, self.expires_at) - now)
+  }
+  // keeps calling connect until it succeeds, the retry budget runs out
+  // or the session expires
+  pub fn connect<C, T, F>(&mut self, clock: &mut C, mut connect: F) -> Result<T, TransportError>
+    where C: Clock, F: FnMut() -> Result<T, TransportError>
+  {
+    loop {
+      match self.poll(clock.now()) {
+        SupervisorAction::Continue | SupervisorAction::Reconnect => match connect() {
+          Ok(transport) => {
+            self.contact(clock.now());
+            return Ok(transport);
+          },
+          Err(_) => self.failure(clock.now()),
+        },
+        SupervisorAction::Wait(secs) => clock.sleep(secs),
+        SupervisorAction::GiveUp => return Err(TransportError::RetriesExhausted),
+        SupervisorAction::Expire => return Err(TransportError::SessionExpired),
+      }
+    }
+  }
+  // a zero wait would spin on a clock that has not moved yet
+  fn wait(&self) -> u64 {
+    cmp::max(self.retry_wait, 1) as u64
+  }
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use std::vec::Vec;
+
+use super::decoder::PacketDecoder;
+use super::error::TransportError;
+use super::transport::Transport;
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+// blocking tcp, either reverse (connect) or bind (listen) style
+#[derive(Debug)]
+pub struct TcpTransport {
+  stream: TcpStream,
+  decoder: PacketDecoder,
+}
+impl TcpTransport {
+  pub fn new(stream: TcpStream) -> Self {
+    TcpTransport {
+      stream,
+      decoder: PacketDecoder::new(),
+    }
+  }
+  pub fn connect<A>(addr: A) -> io::Result<Self>
+    where A: ToSocketAddrs
+  {
+    Ok(TcpTransport::new(TcpStream::connect(addr)?))
+  }
+  // binds and waits for a single connection
+  pub fn listen<A>(addr: A) -> io::Result<Self>
+    where A: ToSocketAddrs
+  {
+    TcpTransport::accept(&TcpListener::bind(addr)?)
+  }
+  pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+    let (stream, _) = listener.accept()?;
+    Ok(TcpTransport::new(stream))
+  }
+  pub fn stream(&self) -> &TcpStream {
+    &self.stream
+  }
+  pub fn decoder(&self) -> &PacketDecoder {
+    &self.decoder
+  }
+  pub fn mut_decoder(&mut self) -> &mut PacketDecoder {
+    &mut self.decoder
+  }
+  // with a timeout set, recv_frame gives up with None instead of blocking forever
+  pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+    self.stream.set_read_timeout(timeout)
+  }
+}
+impl Transport for TcpTransport {
+  fn send_frame(&mut self, frame: &[u8]) -> Result<(), TransportError> {
+    self.stream.write_all(frame)?;
+    self.stream.flush()?;
+    Ok(())
+  }
+  fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, TransportError> {
+    let mut chunk = vec![0x00; READ_CHUNK_SIZE];
+    loop {
+      if let Some(frame) = self.decoder.next_frame()? {
+        return Ok(Some(frame));
+      }
+      match self.stream.read(&mut chunk) {
+        Ok(0) => return Err(TransportError::Closed),
+        Ok(n) => self.decoder.feed(&chunk[..n])?,
+        Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock
+          || err.kind() == io::ErrorKind::TimedOut => return Ok(None),
+        Err(err) => return Err(err.into()),
+      }
+    }
+  }
+}
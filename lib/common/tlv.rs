@@ -1,4 +1,7 @@
 use alloc::vec::*;
+use core::convert::TryFrom;
+use core::ops::{BitAnd, BitOr, Not};
+use super::error::ProtocolError;
 use super::utils::*;
 
 macro_rules! tlv_value {
@@ -10,6 +13,54 @@ macro_rules! tlv_value {
   };
 }
 
+// generates TlvType, both u32 conversions, the name lookups and TlvType::ALL
+// from one `Name = (meta, value),` table so nothing has to be kept in sync
+macro_rules! tlv_types {
+  ($($name:ident = ($meta:expr, $actual:expr),)*) => {
+    #[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+    pub enum TlvType {
+      $($name,)*
+      // any code not listed in the table, kept so it re-serializes unchanged
+      Other(u32),
+    }
+    impl TlvType {
+      pub const ALL: &'static [TlvType] = &[$(TlvType::$name,)*];
+      pub fn name(&self) -> &'static str {
+        match *self {
+          $(TlvType::$name => stringify!($name),)*
+          TlvType::Other(_) => "Other",
+        }
+      }
+      pub fn from_name(name: &str) -> Option<TlvType> {
+        match name {
+          $(stringify!($name) => Some(TlvType::$name),)*
+          _ => None,
+        }
+      }
+    }
+    impl From<TlvType> for u32 {
+      fn from(val: TlvType) -> u32 {
+        match val {
+          $(TlvType::$name => tlv_value!($meta, $actual),)*
+          TlvType::Other(val) => val,
+        }
+      }
+    }
+    impl From<u32> for TlvType {
+      #[allow(non_upper_case_globals)]
+      fn from(val: u32) -> TlvType {
+        // consts so the table values can be used as patterns, a duplicate
+        // value shows up as an unreachable pattern
+        $(const $name: u32 = tlv_value!($meta, $actual);)*
+        match val {
+          $($name => TlvType::$name,)*
+          _ => TlvType::Other(val),
+        }
+      }
+    }
+  };
+}
+
 pub const BASE_RESERVED:               u32 = 0;
 pub const BASE_EXTENSIONS:             u32 = 20000;
 pub const BASE_USER:                   u32 = 40000;
@@ -29,6 +80,75 @@ pub const LOAD_LIBRARY_FLAG_LOCAL:     u32 = 4;          // 1 << 2
 pub const CHANNEL_FLAG_SYNCHRONOUS:    u32 = 1;          // 1 << 0
 pub const CHANNEL_FLAG_COMPRESS:       u32 = 2;          // 1 << 1
 
+// the high 16 bits of a tlv type: one base kind (string, uint, raw, bool,
+// qword) plus any of the compressed/group/complex modifier bits
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct MetaType(u32);
+impl MetaType {
+  pub const NONE: MetaType = MetaType(META_TYPE_NONE);
+  pub const STRING: MetaType = MetaType(META_TYPE_STRING);
+  pub const UINT: MetaType = MetaType(META_TYPE_UINT);
+  pub const RAW: MetaType = MetaType(META_TYPE_RAW);
+  pub const BOOL: MetaType = MetaType(META_TYPE_BOOL);
+  pub const QWORD: MetaType = MetaType(META_TYPE_QWORD);
+  pub const COMPRESSED: MetaType = MetaType(META_TYPE_COMPRESSED);
+  pub const GROUP: MetaType = MetaType(META_TYPE_GROUP);
+  pub const COMPLEX: MetaType = MetaType(META_TYPE_COMPLEX);
+  pub const MODIFIERS: MetaType = MetaType(META_TYPE_COMPRESSED | META_TYPE_GROUP | META_TYPE_COMPLEX);
+  // the value bits are dropped so any full tlv type can be passed in
+  pub fn from_bits(bits: u32) -> Self {
+    MetaType(bits & 0xffff0000)
+  }
+  pub fn bits(&self) -> u32 {
+    self.0
+  }
+  pub fn contains(&self, other: MetaType) -> bool {
+    self.0 & other.0 == other.0
+  }
+  pub fn intersects(&self, other: MetaType) -> bool {
+    self.0 & other.0 != 0
+  }
+  // the kind of value the body holds, with the modifiers masked off
+  pub fn base(&self) -> MetaType {
+    *self & !MetaType::MODIFIERS
+  }
+  pub fn modifiers(&self) -> MetaType {
+    *self & MetaType::MODIFIERS
+  }
+  pub fn is_compressed(&self) -> bool {
+    self.contains(MetaType::COMPRESSED)
+  }
+  pub fn is_group(&self) -> bool {
+    self.contains(MetaType::GROUP)
+  }
+  pub fn is_complex(&self) -> bool {
+    self.contains(MetaType::COMPLEX)
+  }
+}
+impl BitOr for MetaType {
+  type Output = MetaType;
+  fn bitor(self, rhs: MetaType) -> MetaType {
+    MetaType(self.0 | rhs.0)
+  }
+}
+impl BitAnd for MetaType {
+  type Output = MetaType;
+  fn bitand(self, rhs: MetaType) -> MetaType {
+    MetaType(self.0 & rhs.0)
+  }
+}
+impl Not for MetaType {
+  type Output = MetaType;
+  fn not(self) -> MetaType {
+    MetaType::from_bits(!self.0)
+  }
+}
+impl From<MetaType> for u32 {
+  fn from(val: MetaType) -> u32 {
+    val.0
+  }
+}
+
 pub const TLV_PACKET_TYPE_SIZE: usize = 4;
 #[repr(u32)]
 #[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
@@ -39,6 +159,11 @@ pub enum TlvPacketType {
   PlainResponse = 11,
   Invalid       = 0xFFFF,
 }
+impl Default for TlvPacketType {
+  fn default() -> Self {
+    TlvPacketType::new()
+  }
+}
 impl TlvPacketType {
   pub fn new() -> Self {
     TlvPacketType::Request
@@ -56,216 +181,149 @@ impl From<u32> for TlvPacketType {
     }
   }
 }
-impl From<&[u8]> for TlvPacketType {
-  fn from(val: &[u8]) -> TlvPacketType {
-    if val.len() < TLV_PACKET_TYPE_SIZE {
-      panic!{"Tlv::From::<&[u8]> length < {}: {}", TLV_PACKET_TYPE_SIZE, val.len()};
+impl TryFrom<&[u8]> for TlvPacketType {
+  type Error = ProtocolError;
+  fn try_from(val: &[u8]) -> Result<TlvPacketType, ProtocolError> {
+    ProtocolError::check_len(val, TLV_PACKET_TYPE_SIZE)?;
+    let val: u32 = slice_to_u32_ntoh(val)?;
+    match TlvPacketType::from(val) {
+      TlvPacketType::Invalid => Err(ProtocolError::UnknownPacketType(val)),
+      ty => Ok(ty),
     }
-    let val: u32 = slice_to_u32_ntoh(val);
-    TlvPacketType::from(val)
   }
 }
-impl From<Vec<u8>> for TlvPacketType {
-  fn from(val: Vec<u8>) -> TlvPacketType {
-    if val.len() < TLV_PACKET_TYPE_SIZE {
-      panic!{"Tlv::From::<Vec<u8>> length < {}: {}", TLV_PACKET_TYPE_SIZE, val.len()};
-    }
-    let val: u32 = slice_to_u32_ntoh(&val);
-    TlvPacketType::from(val)
+impl TryFrom<Vec<u8>> for TlvPacketType {
+  type Error = ProtocolError;
+  fn try_from(val: Vec<u8>) -> Result<TlvPacketType, ProtocolError> {
+    TlvPacketType::try_from(&val[..])
   }
 }
-impl Into<Vec<u8>> for TlvPacketType {
-  fn into(self) -> Vec<u8> {
-    u32_to_vec_hton(self as u32)
+impl From<TlvPacketType> for Vec<u8> {
+  fn from(val: TlvPacketType) -> Self {
+    u32_to_vec_hton(val as u32)
   }
 }
 
 pub const TLV_TYPE_SIZE: usize = 4;
-#[repr(u32)]
-#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
-pub enum TlvType {
-  Any                        = tlv_value!(META_TYPE_NONE,   0),
-  Method                     = tlv_value!(META_TYPE_STRING, 1),
-  RequestId                  = tlv_value!(META_TYPE_STRING, 2),
-  Exception                  = tlv_value!(META_TYPE_GROUP,  3),
-  Result                     = tlv_value!(META_TYPE_UINT,   4),
+// the single source for every named tlv type, see tlv_types! above
+tlv_types! {
+  Any                        = (META_TYPE_NONE,   0),
+  Method                     = (META_TYPE_STRING, 1),
+  RequestId                  = (META_TYPE_STRING, 2),
+  Exception                  = (META_TYPE_GROUP,  3),
+  Result                     = (META_TYPE_UINT,   4),
   // Arguments
-  String                     = tlv_value!(META_TYPE_STRING, 10),
-  Uint                       = tlv_value!(META_TYPE_UINT,   11),
-  Bool                       = tlv_value!(META_TYPE_BOOL,   12),
+  String                     = (META_TYPE_STRING, 10),
+  Uint                       = (META_TYPE_UINT,   11),
+  Bool                       = (META_TYPE_BOOL,   12),
   // Extended
-  Length                     = tlv_value!(META_TYPE_UINT,   25),
-  Data                       = tlv_value!(META_TYPE_RAW,    26),
-  Flags                      = tlv_value!(META_TYPE_UINT,   27),
+  Length                     = (META_TYPE_UINT,   25),
+  Data                       = (META_TYPE_RAW,    26),
+  Flags                      = (META_TYPE_UINT,   27),
   // Channels
-  ChannelId                  = tlv_value!(META_TYPE_UINT,   50),
-  ChannelType                = tlv_value!(META_TYPE_STRING, 51),
-  ChanneData                 = tlv_value!(META_TYPE_RAW,    52),
-  ChannelClass               = tlv_value!(META_TYPE_UINT,   53),
-  ChannelParentId            = tlv_value!(META_TYPE_UINT,   54),
+  ChannelId                  = (META_TYPE_UINT,   50),
+  ChannelType                = (META_TYPE_STRING, 51),
+  ChanneData                 = (META_TYPE_RAW,    52),
+  ChannelClass               = (META_TYPE_UINT,   53),
+  ChannelParentId            = (META_TYPE_UINT,   54),
   // Channel Extended
-  SeekWhence                 = tlv_value!(META_TYPE_UINT,   70),
-  SeekOffset                 = tlv_value!(META_TYPE_UINT,   71),
-  SeekPos                    = tlv_value!(META_TYPE_UINT,   72),
+  SeekWhence                 = (META_TYPE_UINT,   70),
+  SeekOffset                 = (META_TYPE_UINT,   71),
+  SeekPos                    = (META_TYPE_UINT,   72),
   // Group Ids
-  ExceptionCode              = tlv_value!(META_TYPE_UINT,   300),
-  ExceptionString            = tlv_value!(META_TYPE_STRING, 301),
+  ExceptionCode              = (META_TYPE_UINT,   300),
+  ExceptionString            = (META_TYPE_STRING, 301),
   // Libraries
-  LibraryPath                = tlv_value!(META_TYPE_STRING, 400),
-  TargetPath                 = tlv_value!(META_TYPE_STRING, 401),
-  MigratePid                 = tlv_value!(META_TYPE_UINT,   402),
-  MigratePayloadLength       = tlv_value!(META_TYPE_UINT,   403),
-  MigratePayload             = tlv_value!(META_TYPE_STRING, 404),
-  MigrateArch                = tlv_value!(META_TYPE_UINT,   405),
-  MigrateTechnique           = tlv_value!(META_TYPE_UINT,   406),
-  MigrateBaseAddress         = tlv_value!(META_TYPE_UINT,   407),
-  MigrateEntryPoint          = tlv_value!(META_TYPE_UINT,   408),
-  MigrateSocketPath          = tlv_value!(META_TYPE_STRING, 409),
-  MigrateStubLength          = tlv_value!(META_TYPE_UINT,   410),
-  MigrateStub                = tlv_value!(META_TYPE_STRING, 411),
+  LibraryPath                = (META_TYPE_STRING, 400),
+  TargetPath                 = (META_TYPE_STRING, 401),
+  MigratePid                 = (META_TYPE_UINT,   402),
+  MigratePayloadLength       = (META_TYPE_UINT,   403),
+  MigratePayload             = (META_TYPE_STRING, 404),
+  MigrateArch                = (META_TYPE_UINT,   405),
+  MigrateTechnique           = (META_TYPE_UINT,   406),
+  MigrateBaseAddress         = (META_TYPE_UINT,   407),
+  MigrateEntryPoint          = (META_TYPE_UINT,   408),
+  MigrateSocketPath          = (META_TYPE_STRING, 409),
+  MigrateStubLength          = (META_TYPE_UINT,   410),
+  MigrateStub                = (META_TYPE_STRING, 411),
   // Transports
-  TransportType              = tlv_value!(META_TYPE_UINT,   430),
-  TransportUrl               = tlv_value!(META_TYPE_STRING, 431),
-  TransportUserAgent         = tlv_value!(META_TYPE_STRING, 432),
-  TransportTimeout           = tlv_value!(META_TYPE_UINT,   433),
-  TransportSessionExpiration = tlv_value!(META_TYPE_UINT,   434),
-  TransportCertificateHash   = tlv_value!(META_TYPE_RAW,    435),
-  TransportProxyHost         = tlv_value!(META_TYPE_STRING, 436),
-  TransportProxyUser         = tlv_value!(META_TYPE_STRING, 437),
-  TransportProxyPass         = tlv_value!(META_TYPE_STRING, 438),
-  TransportRetryTotal        = tlv_value!(META_TYPE_UINT,   439),
-  TransportRetryWait         = tlv_value!(META_TYPE_UINT,   440),
-  TransportHeaders           = tlv_value!(META_TYPE_STRING, 441),
-  TransportGroup             = tlv_value!(META_TYPE_GROUP,  442),
+  TransportType              = (META_TYPE_UINT,   430),
+  TransportUrl               = (META_TYPE_STRING, 431),
+  TransportUserAgent         = (META_TYPE_STRING, 432),
+  TransportTimeout           = (META_TYPE_UINT,   433),
+  TransportSessionExpiration = (META_TYPE_UINT,   434),
+  TransportCertificateHash   = (META_TYPE_RAW,    435),
+  TransportProxyHost         = (META_TYPE_STRING, 436),
+  TransportProxyUser         = (META_TYPE_STRING, 437),
+  TransportProxyPass         = (META_TYPE_STRING, 438),
+  TransportRetryTotal        = (META_TYPE_UINT,   439),
+  TransportRetryWait         = (META_TYPE_UINT,   440),
+  TransportHeaders           = (META_TYPE_STRING, 441),
+  TransportGroup             = (META_TYPE_GROUP,  442),
   // Ident
-  MachineId                  = tlv_value!(META_TYPE_STRING, 460),
-  Uuid                       = tlv_value!(META_TYPE_RAW,    461),
-  SessionGuid                = tlv_value!(META_TYPE_RAW,    462),
+  MachineId                  = (META_TYPE_STRING, 460),
+  Uuid                       = (META_TYPE_RAW,    461),
+  SessionGuid                = (META_TYPE_RAW,    462),
   // Encryption
-  RsaPubKey                  = tlv_value!(META_TYPE_STRING, 550),
-  SymetricKeyType            = tlv_value!(META_TYPE_UINT,   551),
-  SymetricKey                = tlv_value!(META_TYPE_RAW,    552),
-  EncryptedSymetricKey       = tlv_value!(META_TYPE_RAW,    553),
+  RsaPubKey                  = (META_TYPE_STRING, 550),
+  SymetricKeyType            = (META_TYPE_UINT,   551),
+  SymetricKey                = (META_TYPE_RAW,    552),
+  EncryptedSymetricKey       = (META_TYPE_RAW,    553),
   // Pivots
-  PivotId                    = tlv_value!(META_TYPE_RAW,    650),
-  PivotStageData             = tlv_value!(META_TYPE_RAW,    651),
-  PivotStageDataSize         = tlv_value!(META_TYPE_UINT,   652),
-  PivotNamedPipeName         = tlv_value!(META_TYPE_STRING, 653),
+  PivotId                    = (META_TYPE_RAW,    650),
+  PivotStageData             = (META_TYPE_RAW,    651),
+  PivotStageDataSize         = (META_TYPE_UINT,   652),
+  PivotNamedPipeName         = (META_TYPE_STRING, 653),
   // Peering
-  PeerHost                   = tlv_value!(META_TYPE_STRING, 1500),
-  PeerPort                   = tlv_value!(META_TYPE_UINT,   1501),
-  LocalHost                  = tlv_value!(META_TYPE_STRING, 1502),
-  LocalPort                  = tlv_value!(META_TYPE_UINT,   1503),
+  PeerHost                   = (META_TYPE_STRING, 1500),
+  PeerPort                   = (META_TYPE_UINT,   1501),
+  LocalHost                  = (META_TYPE_STRING, 1502),
+  LocalPort                  = (META_TYPE_UINT,   1503),
   // Generic
-  Extensions                 = tlv_value!(META_TYPE_COMPLEX, BASE_EXTENSIONS),
-  User                       = tlv_value!(META_TYPE_COMPLEX, BASE_USER),
-  Temp                       = tlv_value!(META_TYPE_COMPLEX, BASE_TEMP),
-  Invalid                    = 0xFFFFFFFF
+  Extensions                 = (META_TYPE_COMPLEX, BASE_EXTENSIONS),
+  User                       = (META_TYPE_COMPLEX, BASE_USER),
+  Temp                       = (META_TYPE_COMPLEX, BASE_TEMP),
+  Invalid                    = (META_TYPE_NONE,   0xFFFFFFFF),
 }
 impl TlvType {
-  pub fn get_type(&self) -> u32 {
-    *self as u32 & 0xffff0000
+  // Invalid's all-ones sentinel would otherwise carry every modifier bit
+  pub fn meta_type(&self) -> MetaType {
+    match *self {
+      TlvType::Invalid => MetaType::NONE,
+      ty => MetaType::from_bits(u32::from(ty)),
+    }
   }
   pub fn get_value(&self) -> u32 {
-    *self as u32 & 0x0000ffff
+    u32::from(*self) & 0x0000ffff
   }
   pub fn is_compressed(&self) -> bool {
-    self.get_type() == META_TYPE_COMPRESSED
+    self.meta_type().is_compressed()
   }
-}
-// instead of Into, use `as u32` to get value
-impl From<u32> for TlvType {
-  fn from(val: u32) -> TlvType {
-    match val {
-      0x0        => TlvType::Any,
-      0x10001    => TlvType::Method,
-      0x10002    => TlvType::RequestId,
-      0x40000003 => TlvType::Exception,
-      0x20004    => TlvType::Result,
-      0x1000A    => TlvType::String,
-      0x2000B    => TlvType::Uint,
-      0x8000C    => TlvType::Bool,
-      0x20019    => TlvType::Length,
-      0x4001A    => TlvType::Data,
-      0x2001B    => TlvType::Flags,
-      0x20032    => TlvType::ChannelId,
-      0x10033    => TlvType::ChannelType,
-      0x40034    => TlvType::ChanneData,
-      0x20035    => TlvType::ChannelClass,
-      0x20036    => TlvType::ChannelParentId,
-      0x20046    => TlvType::SeekWhence,
-      0x20047    => TlvType::SeekOffset,
-      0x20048    => TlvType::SeekPos,
-      0x2012C    => TlvType::ExceptionCode,
-      0x1012D    => TlvType::ExceptionString,
-      0x10190    => TlvType::LibraryPath,
-      0x10191    => TlvType::TargetPath,
-      0x20192    => TlvType::MigratePid,
-      0x20193    => TlvType::MigratePayloadLength,
-      0x10194    => TlvType::MigratePayload,
-      0x20195    => TlvType::MigrateArch,
-      0x20196    => TlvType::MigrateTechnique,
-      0x20197    => TlvType::MigrateBaseAddress,
-      0x20198    => TlvType::MigrateEntryPoint,
-      0x10199    => TlvType::MigrateSocketPath,
-      0x2019A    => TlvType::MigrateStubLength,
-      0x1019B    => TlvType::MigrateStub,
-      0x201AE    => TlvType::TransportType,
-      0x101AF    => TlvType::TransportUrl,
-      0x101B0    => TlvType::TransportUserAgent,
-      0x201B1    => TlvType::TransportTimeout,
-      0x201B2    => TlvType::TransportSessionExpiration,
-      0x401B3    => TlvType::TransportCertificateHash,
-      0x101B4    => TlvType::TransportProxyHost,
-      0x101B5    => TlvType::TransportProxyUser,
-      0x101B6    => TlvType::TransportProxyPass,
-      0x201B7    => TlvType::TransportRetryTotal,
-      0x201B8    => TlvType::TransportRetryWait,
-      0x101B9    => TlvType::TransportHeaders,
-      0x400001BA => TlvType::TransportGroup,
-      0x101CC    => TlvType::MachineId,
-      0x401CD    => TlvType::Uuid,
-      0x401CE    => TlvType::SessionGuid,
-      0x10226    => TlvType::RsaPubKey,
-      0x20227    => TlvType::SymetricKeyType,
-      0x40228    => TlvType::SymetricKey,
-      0x40229    => TlvType::EncryptedSymetricKey,
-      0x4028A    => TlvType::PivotId,
-      0x4028B    => TlvType::PivotStageData,
-      0x2028C    => TlvType::PivotStageDataSize,
-      0x1028D    => TlvType::PivotNamedPipeName,
-      0x105DC    => TlvType::PeerHost,
-      0x205DD    => TlvType::PeerPort,
-      0x105DE    => TlvType::LocalHost,
-      0x205DF    => TlvType::LocalPort,
-      0x80004E20 => TlvType::Extensions,
-      0x80009C40 => TlvType::User,
-      0x8000EA60 => TlvType::Temp,
-      _          => TlvType::Invalid,
-    }
+  pub fn is_group(&self) -> bool {
+    self.meta_type().is_group()
+  }
+  // same value under a different meta type, unlisted codes become Other
+  pub fn with_meta(&self, meta: MetaType) -> TlvType {
+    TlvType::from(meta.bits() | self.get_value())
   }
 }
-impl From<&[u8]> for TlvType {
-  fn from(val: &[u8]) -> TlvType {
-    if val.len() < TLV_TYPE_SIZE {
-      panic!{"TlvType::From:<&[u8]> length < {}: {}", TLV_TYPE_SIZE, val.len()};
-    }
-    let ty: u32 = slice_to_u32_ntoh(val);
-    TlvType::from(ty)
+impl TryFrom<&[u8]> for TlvType {
+  type Error = ProtocolError;
+  fn try_from(val: &[u8]) -> Result<TlvType, ProtocolError> {
+    ProtocolError::check_len(val, TLV_TYPE_SIZE)?;
+    let ty: u32 = slice_to_u32_ntoh(val)?;
+    Ok(TlvType::from(ty))
   }
 }
-impl From<Vec<u8>> for TlvType {
-  fn from(val: Vec<u8>) -> TlvType {
-    if val.len() < TLV_TYPE_SIZE {
-      panic!{"TlvType::From:<Vec<u8>> length < {}: {}", TLV_TYPE_SIZE, val.len()};
-    }
-    let ty: u32 = slice_to_u32_ntoh(&val);
-    TlvType::from(ty)
+impl TryFrom<Vec<u8>> for TlvType {
+  type Error = ProtocolError;
+  fn try_from(val: Vec<u8>) -> Result<TlvType, ProtocolError> {
+    TlvType::try_from(&val[..])
   }
 }
-impl Into<Vec<u8>> for TlvType {
-  fn into(self) -> Vec<u8> {
-    u32_to_vec_hton(self as u32)
+impl From<TlvType> for Vec<u8> {
+  fn from(val: TlvType) -> Self {
+    u32_to_vec_hton(u32::from(val))
   }
 }
 pub const TLV_HEADER_SIZE: usize = 8;
@@ -274,6 +332,11 @@ pub struct TlvHeader {
   length: u32,
   type_: TlvType,
 }
+impl Default for TlvHeader {
+  fn default() -> Self {
+    TlvHeader::new()
+  }
+}
 impl TlvHeader {
   pub fn new() -> Self {
     TlvHeader {
@@ -309,63 +372,176 @@ impl TlvHeader {
     self.type_ = ty.into();
   }
 }
-impl From<&[u8]> for TlvHeader {
-  fn from(val: &[u8]) -> TlvHeader {
-    if val.len() < TLV_HEADER_SIZE {
-      panic!{"TlvHeader::From::<&[u8]> length"}
-    }
-    let length: u32 = slice_to_u32_ntoh(val);
+impl TryFrom<&[u8]> for TlvHeader {
+  type Error = ProtocolError;
+  fn try_from(val: &[u8]) -> Result<TlvHeader, ProtocolError> {
+    ProtocolError::check_len(val, TLV_HEADER_SIZE)?;
+    let length: u32 = slice_to_u32_ntoh(val)?;
 
     let type_: &[u8] = &val[4..];
-    let type_: TlvType = type_.into();
+    let type_: TlvType = TlvType::try_from(type_)?;
 
-    TlvHeader {
-      length: length,
-      type_: type_,
-    }
+    Ok(TlvHeader {
+      length,
+      type_,
+    })
   }
 }
-impl From<Vec<u8>> for TlvHeader {
-  fn from(val: Vec<u8>) -> TlvHeader {
-    if val.len() < TLV_HEADER_SIZE {
-      panic!{"TlvHeader::From::<Vec<u8>> length"}
-    }
-    let header: &[u8] = &val[..];
-    let header: TlvHeader = header.into();
-    header
+impl TryFrom<Vec<u8>> for TlvHeader {
+  type Error = ProtocolError;
+  fn try_from(val: Vec<u8>) -> Result<TlvHeader, ProtocolError> {
+    TlvHeader::try_from(&val[..])
   }
 }
-impl Into<Vec<u8>> for TlvHeader {
-  fn into(self) -> Vec<u8> {
-    let mut vec: Vec<u8> = u32_to_vec_hton(self.length);
-    let mut ty: Vec<u8> = self.type_.into();
+impl From<TlvHeader> for Vec<u8> {
+  fn from(val: TlvHeader) -> Self {
+    let mut vec: Vec<u8> = u32_to_vec_hton(val.length);
+    let mut ty: Vec<u8> = val.type_.into();
     vec.append(&mut ty);
     vec
   }
 }
 
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub enum TlvValue<'a> {
+  None,
+  String(&'a str),
+  Uint(u32),
+  Qword(u64),
+  Bool(bool),
+  Raw(&'a [u8]),
+  Group(&'a [Tlv]),
+}
+
+// deepest group nesting accepted when parsing
+pub const TLV_MAX_DEPTH: usize = 16;
+// group tlvs keep their members in children and leave buffer empty
 #[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
 pub struct Tlv {
   header: TlvHeader,
   buffer: Vec<u8>,
+  children: Vec<Tlv>,
+}
+impl Default for Tlv {
+  fn default() -> Self {
+    Tlv::new()
+  }
 }
 impl Tlv {
+  pub fn new() -> Self {
+    Tlv {
+      header: TlvHeader::new(),
+      buffer: Vec::new(),
+      children: Vec::new(),
+    }
+  }
+  // only group types, anything else would parse back as a plain value
+  pub fn group(ty: TlvType) -> Result<Self, ProtocolError> {
+    if !ty.is_group() {
+      return Err(ProtocolError::TypeMismatch {
+        expected: MetaType::GROUP,
+        actual: ty.meta_type(),
+      });
+    }
+    Ok(Tlv::new().set_header(TlvHeader::new().set_type(ty)))
+  }
+  // strings are sent with their NUL terminator, as meterpreter expects
+  pub fn string(ty: TlvType, val: &str) -> Result<Self, ProtocolError> {
+    let mut buf: Vec<u8> = Vec::with_capacity(val.len() + 1);
+    buf.extend_from_slice(val.as_bytes());
+    buf.push(0);
+    Tlv::typed(ty, MetaType::STRING, buf)
+  }
+  pub fn uint(ty: TlvType, val: u32) -> Result<Self, ProtocolError> {
+    Tlv::typed(ty, MetaType::UINT, u32_to_vec_hton(val))
+  }
+  pub fn qword(ty: TlvType, val: u64) -> Result<Self, ProtocolError> {
+    Tlv::typed(ty, MetaType::QWORD, u64_to_vec_hton(val))
+  }
+  pub fn bool(ty: TlvType, val: bool) -> Result<Self, ProtocolError> {
+    Tlv::typed(ty, MetaType::BOOL, [val as u8].to_vec())
+  }
+  pub fn raw<B>(ty: TlvType, val: B) -> Result<Self, ProtocolError>
+    where B: Into<Vec<u8>>
+  {
+    Tlv::typed(ty, MetaType::RAW, val.into())
+  }
+  fn typed(ty: TlvType, meta: MetaType, buf: Vec<u8>) -> Result<Self, ProtocolError> {
+    Tlv::check_meta(ty, meta)?;
+    let header = TlvHeader::new()
+      .set_type(ty);
+    Ok(Tlv::new().set_header(header).set_buffer(buf))
+  }
+  // compressed bodies hold zlib data, so they never match a plain meta type
+  fn check_meta(ty: TlvType, meta: MetaType) -> Result<(), ProtocolError> {
+    let actual = ty.meta_type().base() | (ty.meta_type() & MetaType::COMPRESSED);
+    if actual != meta {
+      return Err(ProtocolError::TypeMismatch {
+        expected: meta,
+        actual,
+      });
+    }
+    Ok(())
+  }
+  pub fn value(&self) -> Result<TlvValue<'_>, ProtocolError> {
+    if self.is_group() {
+      return Ok(TlvValue::Group(&self.children))
+    }
+    match self.header.get_type().meta_type().base() {
+      MetaType::STRING => self.as_str().map(TlvValue::String),
+      MetaType::UINT => self.as_u32().map(TlvValue::Uint),
+      MetaType::QWORD => self.as_u64().map(TlvValue::Qword),
+      MetaType::BOOL => self.as_bool().map(TlvValue::Bool),
+      MetaType::RAW => self.as_bytes().map(TlvValue::Raw),
+      _ => Ok(TlvValue::None),
+    }
+  }
+  // text up to the first NUL, the terminator itself is required
+  pub fn as_str(&self) -> Result<&str, ProtocolError> {
+    Tlv::check_meta(self.header.get_type(), MetaType::STRING)?;
+    let end = self.buffer.iter()
+      .position(|b| *b == 0)
+      .ok_or(ProtocolError::InvalidString)?;
+    core::str::from_utf8(&self.buffer[..end])
+      .map_err(|_| ProtocolError::InvalidString)
+  }
+  pub fn as_u32(&self) -> Result<u32, ProtocolError> {
+    Tlv::check_meta(self.header.get_type(), MetaType::UINT)?;
+    slice_to_u32_ntoh(&self.buffer)
+  }
+  pub fn as_u64(&self) -> Result<u64, ProtocolError> {
+    Tlv::check_meta(self.header.get_type(), MetaType::QWORD)?;
+    slice_to_u64_ntoh(&self.buffer)
+  }
+  pub fn as_bool(&self) -> Result<bool, ProtocolError> {
+    Tlv::check_meta(self.header.get_type(), MetaType::BOOL)?;
+    ProtocolError::check_len(&self.buffer, 1)?;
+    Ok(self.buffer[0] != 0)
+  }
+  // raw body of any non-group, uncompressed tlv
+  pub fn as_bytes(&self) -> Result<&[u8], ProtocolError> {
+    if self.is_group() || self.header.get_type().is_compressed() {
+      return Err(ProtocolError::TypeMismatch {
+        expected: MetaType::RAW,
+        actual: self.header.get_type().meta_type(),
+      });
+    }
+    Ok(&self.buffer)
+  }
   pub fn header(&self) -> &TlvHeader {
     &self.header
   }
   pub fn mut_header(&mut self) -> &mut TlvHeader {
     &mut self.header
   }
-  pub fn set_header<T>(mut self, header: T) -> Self
-    where T: Into<TlvHeader>
-  {
+  pub fn set_header(mut self, header: TlvHeader) -> Self {
     self.set_header_ref(header);
     self
   }
-  pub fn set_header_ref<T>(&mut self, header: T)
-    where T: Into<TlvHeader>
-  {
-    self.header = header.into();
+  // the length is always recomputed from the body, only the type is kept
+  pub fn set_header_ref(&mut self, header: TlvHeader) {
+    self.header = header;
+    self.refresh_length();
   }
   pub fn buffer(&self) -> &Vec<u8> {
     self.buffer.as_ref()
@@ -383,57 +559,216 @@ impl Tlv {
     where B: Into<Vec<u8>>
   {
     self.buffer = buf.into();
+    self.refresh_length();
   }
-  pub fn slice_to_tlv_vec(slice: &[u8]) -> Option<Vec<Tlv>> {
-    if slice.len() < TLV_HEADER_SIZE {
-      return None
+  pub fn is_group(&self) -> bool {
+    self.header.get_type().is_group()
+  }
+  pub fn children(&self) -> &Vec<Tlv> {
+    &self.children
+  }
+  pub fn mut_children(&mut self) -> &mut Vec<Tlv> {
+    &mut self.children
+  }
+  pub fn add_child(mut self, tlv: Tlv) -> Self {
+    self.add_child_ref(tlv);
+    self
+  }
+  // children are trusted to carry their own length, so building a tree
+  // never walks back down it
+  pub fn add_child_ref(&mut self, tlv: Tlv) {
+    let length = self.header.length() + tlv.header().length();
+    self.header.set_length_ref(length);
+    self.children.push(tlv);
+  }
+  pub fn find_child(&self, ty: TlvType) -> Option<&Tlv> {
+    self.children.iter().find(|tlv| tlv.header().get_type() == ty)
+  }
+  // length on the wire, groups are summed from their children
+  pub fn encoded_length(&self) -> u32 {
+    if !self.is_group() {
+      return (TLV_HEADER_SIZE + self.buffer.len()) as u32
     }
-    let mut vec: Vec<Tlv> = Vec::with_capacity(slice.len() / TLV_HEADER_SIZE);
-    while TLV_HEADER_SIZE <= slice.len() {
-      let (header, slice) = slice.split_at(TLV_HEADER_SIZE);
-      let header: TlvHeader = header.into();
-      let (buffer, slice) = slice.split_at(header.length() as usize);
-      let buffer: Vec<u8> = buffer.to_vec();
-      let tlv = Tlv {
-        header: header,
-        buffer: buffer,
-      };
-      vec.push(tlv);
+    self.children.iter().fold(TLV_HEADER_SIZE as u32, |sum, tlv| {
+      sum + tlv.encoded_length()
+    })
+  }
+  // only needed after editing through mut_buffer/mut_children/mut_header,
+  // serialization syncs once before writing
+  pub fn sync_length(&mut self) {
+    for child in self.children.iter_mut() {
+      child.sync_length();
     }
-
-    Some(vec)
+    self.refresh_length();
+  }
+  // checks the stored header lengths against the bodies they describe
+  pub fn validate(&self) -> Result<(), ProtocolError> {
+    self.children.iter().try_for_each(Tlv::validate)?;
+    if self.header.length() != self.encoded_length() {
+      return Err(ProtocolError::LengthMismatch {
+        declared: self.header.length(),
+        actual: self.encoded_length(),
+      });
+    }
+    Ok(())
+  }
+  // own length from the body and the children's stored lengths
+  fn refresh_length(&mut self) {
+    let length = match self.is_group() {
+      true => self.children.iter().fold(TLV_HEADER_SIZE as u32, |sum, tlv| sum + tlv.header().length()),
+      false => (TLV_HEADER_SIZE + self.buffer.len()) as u32,
+    };
+    self.header.set_length_ref(length);
+  }
+  // writes the stored lengths as they are, callers sync first
+  fn append_to(self, vec: &mut Vec<u8>) {
+    vec.append(&mut self.header.into());
+    if self.header.get_type().is_group() {
+      for child in self.children.into_iter() {
+        child.append_to(vec);
+      }
+      return
+    }
+    vec.extend_from_slice(&self.buffer);
+  }
+  // walks a packet payload, where each tlv length counts its own header
+  pub fn slice_to_tlv_vec(slice: &[u8]) -> Result<Vec<Tlv>, ProtocolError> {
+    Tlv::slice_to_tlv_vec_depth(slice, 0)
+  }
+  fn slice_to_tlv_vec_depth(slice: &[u8], depth: usize) -> Result<Vec<Tlv>, ProtocolError> {
+    TlvIter::new(slice)
+      .map(|tlv| tlv.and_then(|tlv| Tlv::from_ref(tlv, depth)))
+      .collect()
+  }
+  fn from_ref(val: TlvRef, depth: usize) -> Result<Tlv, ProtocolError> {
+    if !val.header.get_type().is_group() {
+      return Ok(Tlv {
+        header: val.header,
+        buffer: val.buffer.to_vec(),
+        children: Vec::new(),
+      })
+    }
+    if depth >= TLV_MAX_DEPTH {
+      return Err(ProtocolError::NestedTooDeep { depth: TLV_MAX_DEPTH });
+    }
+    Ok(Tlv {
+      header: val.header,
+      buffer: Vec::new(),
+      children: Tlv::slice_to_tlv_vec_depth(val.buffer, depth + 1)?,
+    })
+  }
+}
+impl TryFrom<&[u8]> for Tlv {
+  type Error = ProtocolError;
+  fn try_from(val: &[u8]) -> Result<Tlv, ProtocolError> {
+    TlvRef::try_from(val)?.to_tlv()
+  }
+}
+impl TryFrom<Vec<u8>> for Tlv {
+  type Error = ProtocolError;
+  fn try_from(val: Vec<u8>) -> Result<Tlv, ProtocolError> {
+    Tlv::try_from(&val[..])
   }
 }
-impl From<&[u8]> for Tlv {
-  fn from(val: &[u8]) -> Tlv {
-    if val.len() < TLV_HEADER_SIZE {
-      panic!{"Tlv::From:<&[u8]> length < {}: {}", TLV_HEADER_SIZE, val.len()};
+impl From<Tlv> for Vec<u8> {
+  fn from(mut val: Tlv) -> Self {
+    val.sync_length();
+    let mut tlv: Vec<u8> = Vec::with_capacity(val.header.length() as usize);
+    val.append_to(&mut tlv);
+    tlv
+  }
+}
+// borrowed view of a tlv, the buffer points into the parsed bytes
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct TlvRef<'a> {
+  header: TlvHeader,
+  buffer: &'a [u8],
+}
+impl<'a> TlvRef<'a> {
+  pub fn header(&self) -> &TlvHeader {
+    &self.header
+  }
+  pub fn buffer(&self) -> &'a [u8] {
+    self.buffer
+  }
+  // members of a group tlv, empty for anything else
+  pub fn children(&self) -> TlvIter<'a> {
+    match self.header.get_type().is_group() {
+      true => TlvIter::new(self.buffer),
+      false => TlvIter::new(&[]),
     }
+  }
+  pub fn to_tlv(&self) -> Result<Tlv, ProtocolError> {
+    Tlv::try_from(*self)
+  }
+}
+impl<'a> TryFrom<&'a [u8]> for TlvRef<'a> {
+  type Error = ProtocolError;
+  fn try_from(val: &'a [u8]) -> Result<TlvRef<'a>, ProtocolError> {
+    ProtocolError::check_len(val, TLV_HEADER_SIZE)?;
 
-    let (header, buffer) = val.split_at(TLV_HEADER_SIZE);
-    let header: TlvHeader = header.into();
-    let buffer: Vec<u8> = buffer.to_vec();
-
-    Tlv {
-      header: header,
-      buffer: buffer,
+    let header: TlvHeader = TlvHeader::try_from(&val[..TLV_HEADER_SIZE])?;
+    let length = header.length() as usize;
+    if length < TLV_HEADER_SIZE {
+      return Err(ProtocolError::LengthUnderflow {
+        length: header.length(),
+        minimum: TLV_HEADER_SIZE,
+      });
+    }
+    if length > val.len() {
+      return Err(ProtocolError::LengthOverflow {
+        length: header.length(),
+        available: val.len(),
+      });
     }
+
+    Ok(TlvRef {
+      header,
+      buffer: &val[TLV_HEADER_SIZE..length],
+    })
   }
 }
-impl From<Vec<u8>> for Tlv {
-  fn from(val: Vec<u8>) -> Tlv {
-    if val.len() < TLV_HEADER_SIZE {
-      panic!{"Tlv::From:<Vec<u8>> length < {}: {}", TLV_HEADER_SIZE, val.len()};
+impl<'a> TryFrom<TlvRef<'a>> for Tlv {
+  type Error = ProtocolError;
+  fn try_from(val: TlvRef<'a>) -> Result<Tlv, ProtocolError> {
+    Tlv::from_ref(val, 0)
+  }
+}
+
+// lazily walks a payload, stopping after the first malformed tlv
+#[derive(Copy,Clone,Debug)]
+pub struct TlvIter<'a> {
+  slice: &'a [u8],
+  failed: bool,
+}
+impl<'a> TlvIter<'a> {
+  pub fn new(slice: &'a [u8]) -> Self {
+    TlvIter {
+      slice,
+      failed: false,
     }
-    let val: &[u8] = &val[..];
-    let tlv: Tlv = val.into();
-    tlv
+  }
+  // bytes not yet consumed by the iterator
+  pub fn remaining(&self) -> &'a [u8] {
+    self.slice
   }
 }
-impl Into<Vec<u8>> for Tlv {
-  fn into(self) -> Vec<u8> {
-    let mut tlv: Vec<u8> = self.header.into();
-    tlv.extend(self.buffer.iter().clone());
-    tlv
+impl<'a> Iterator for TlvIter<'a> {
+  type Item = Result<TlvRef<'a>, ProtocolError>;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.failed || self.slice.is_empty() {
+      return None
+    }
+    match TlvRef::try_from(self.slice) {
+      Ok(tlv) => {
+        self.slice = &self.slice[tlv.header().length() as usize..];
+        Some(Ok(tlv))
+      },
+      Err(e) => {
+        self.failed = true;
+        Some(Err(e))
+      },
+    }
   }
-}
\ No newline at end of file
+}
+impl<'a> core::iter::FusedIterator for TlvIter<'a> {}
@@ -0,0 +1,278 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::command::*;
+use super::error::{ProtocolError, TransportError};
+use super::packet::Packet;
+use super::tlv::*;
+
+// meterpreter's defaults, all in seconds
+pub const DEFAULT_COMM_TIMEOUT: u32 = 300;
+pub const DEFAULT_SESSION_EXPIRATION: u32 = 7 * 24 * 60 * 60;
+pub const DEFAULT_RETRY_TOTAL: u32 = 60 * 60;
+pub const DEFAULT_RETRY_WAIT: u32 = 10;
+
+// moves whole encoded packets, the session does the encoding
+pub trait Transport {
+  fn send_frame(&mut self, frame: &[u8]) -> Result<(), TransportError>;
+  // None when nothing has arrived yet, polling transports may come up empty
+  fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, TransportError>;
+}
+
+// one TransportGroup: where to connect and how hard to try
+#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct TransportConfig {
+  url: String,
+  comm_timeout: u32,
+  // session wide, so only present when the group carried it
+  session_expiration: Option<u32>,
+  retry_total: u32,
+  retry_wait: u32,
+  // transport specific tlvs (user agent, proxy, ...) kept as they came
+  options: Vec<Tlv>,
+}
+impl TransportConfig {
+  pub fn new(url: &str) -> Self {
+    TransportConfig {
+      url: url.to_string(),
+      comm_timeout: DEFAULT_COMM_TIMEOUT,
+      session_expiration: None,
+      retry_total: DEFAULT_RETRY_TOTAL,
+      retry_wait: DEFAULT_RETRY_WAIT,
+      options: Vec::new(),
+    }
+  }
+  // reads the Transport* tlvs out of a group or a request, ignoring the rest
+  pub fn from_tlvs<'a, I>(tlvs: I) -> Result<Self, ProtocolError>
+    where I: IntoIterator<Item = &'a Tlv>
+  {
+    let tlvs: Vec<&Tlv> = tlvs.into_iter().collect();
+    let url = tlvs.iter()
+      .find(|tlv| tlv.header().get_type() == TlvType::TransportUrl)
+      .ok_or(ProtocolError::MissingTlv(TlvType::TransportUrl))?;
+    let mut config = TransportConfig::new(url.as_str()?);
+    for tlv in tlvs.iter() {
+      match tlv.header().get_type() {
+        TlvType::TransportTimeout => config.comm_timeout = tlv.as_u32()?,
+        TlvType::TransportSessionExpiration => config.session_expiration = Some(tlv.as_u32()?),
+        TlvType::TransportRetryTotal => config.retry_total = tlv.as_u32()?,
+        TlvType::TransportRetryWait => config.retry_wait = tlv.as_u32()?,
+        TlvType::TransportType
+          | TlvType::TransportUserAgent
+          | TlvType::TransportCertificateHash
+          | TlvType::TransportProxyHost
+          | TlvType::TransportProxyUser
+          | TlvType::TransportProxyPass
+          | TlvType::TransportHeaders => config.options.push((*tlv).clone()),
+        _ => {},
+      }
+    }
+    Ok(config)
+  }
+  pub fn url(&self) -> &str {
+    &self.url
+  }
+  pub fn comm_timeout(&self) -> u32 {
+    self.comm_timeout
+  }
+  pub fn set_comm_timeout(mut self, secs: u32) -> Self {
+    self.set_comm_timeout_ref(secs);
+    self
+  }
+  pub fn set_comm_timeout_ref(&mut self, secs: u32) {
+    self.comm_timeout = secs;
+  }
+  pub fn session_expiration(&self) -> Option<u32> {
+    self.session_expiration
+  }
+  pub fn set_session_expiration(mut self, secs: u32) -> Self {
+    self.set_session_expiration_ref(secs);
+    self
+  }
+  pub fn set_session_expiration_ref(&mut self, secs: u32) {
+    self.session_expiration = Some(secs);
+  }
+  pub fn retry_total(&self) -> u32 {
+    self.retry_total
+  }
+  pub fn set_retry_total(mut self, secs: u32) -> Self {
+    self.set_retry_total_ref(secs);
+    self
+  }
+  pub fn set_retry_total_ref(&mut self, secs: u32) {
+    self.retry_total = secs;
+  }
+  pub fn retry_wait(&self) -> u32 {
+    self.retry_wait
+  }
+  pub fn set_retry_wait(mut self, secs: u32) -> Self {
+    self.set_retry_wait_ref(secs);
+    self
+  }
+  pub fn set_retry_wait_ref(&mut self, secs: u32) {
+    self.retry_wait = secs;
+  }
+  pub fn options(&self) -> &Vec<Tlv> {
+    &self.options
+  }
+  pub fn add_option(mut self, tlv: Tlv) -> Self {
+    self.add_option_ref(tlv);
+    self
+  }
+  pub fn add_option_ref(&mut self, tlv: Tlv) {
+    self.options.push(tlv);
+  }
+  // the url plus the options, which is what HttpConfig::from_tlvs reads
+  pub fn tlvs(&self) -> Result<Vec<Tlv>, ProtocolError> {
+    let mut tlvs: Vec<Tlv> = Vec::with_capacity(self.options.len() + 1);
+    tlvs.push(Tlv::string(TlvType::TransportUrl, &self.url)?);
+    tlvs.extend(self.options.iter().cloned());
+    Ok(tlvs)
+  }
+  pub fn to_tlv(&self) -> Result<Tlv, ProtocolError> {
+    let mut group = Tlv::group(TlvType::TransportGroup)?
+      .add_child(Tlv::string(TlvType::TransportUrl, &self.url)?)
+      .add_child(Tlv::uint(TlvType::TransportTimeout, self.comm_timeout)?)
+      .add_child(Tlv::uint(TlvType::TransportRetryTotal, self.retry_total)?)
+      .add_child(Tlv::uint(TlvType::TransportRetryWait, self.retry_wait)?);
+    if let Some(secs) = self.session_expiration {
+      group.add_child_ref(Tlv::uint(TlvType::TransportSessionExpiration, secs)?);
+    }
+    for tlv in self.options.iter() {
+      group.add_child_ref(tlv.clone());
+    }
+    Ok(group)
+  }
+}
+impl<'a> TryFrom<&'a Tlv> for TransportConfig {
+  type Error = ProtocolError;
+  fn try_from(group: &'a Tlv) -> Result<TransportConfig, ProtocolError> {
+    if group.header().get_type() != TlvType::TransportGroup {
+      return Err(ProtocolError::MissingTlv(TlvType::TransportGroup));
+    }
+    TransportConfig::from_tlvs(group.children().iter())
+  }
+}
+
+// the transports a session can fall back on, with one of them active
+#[derive(Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct TransportList {
+  configs: Vec<TransportConfig>,
+  current: usize,
+  // set by commands that move to another transport, see take_switch
+  switch_pending: bool,
+}
+impl Default for TransportList {
+  fn default() -> Self {
+    TransportList::new()
+  }
+}
+impl TransportList {
+  pub fn new() -> Self {
+    TransportList {
+      configs: Vec::new(),
+      current: 0,
+      switch_pending: false,
+    }
+  }
+  pub fn configs(&self) -> &Vec<TransportConfig> {
+    &self.configs
+  }
+  pub fn len(&self) -> usize {
+    self.configs.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.configs.is_empty()
+  }
+  pub fn current(&self) -> Option<&TransportConfig> {
+    self.configs.get(self.current)
+  }
+  pub fn current_index(&self) -> usize {
+    self.current
+  }
+  pub fn add_transport(mut self, config: TransportConfig) -> Self {
+    self.add_transport_ref(config);
+    self
+  }
+  // urls are unique, a config for one already listed replaces it in place
+  pub fn add_transport_ref(&mut self, config: TransportConfig) {
+    match self.find(config.url()) {
+      Some(index) => self.configs[index] = config,
+      None => self.configs.push(config),
+    }
+  }
+  pub fn find(&self, url: &str) -> Option<usize> {
+    self.configs.iter().position(|config| config.url() == url)
+  }
+  pub fn select(&mut self, index: usize) -> Option<&TransportConfig> {
+    if index >= self.configs.len() {
+      return None;
+    }
+    self.current = index;
+    self.configs.get(index)
+  }
+  // both wrap around, which is also how failures move through the list
+  pub fn select_next(&mut self) -> Option<&TransportConfig> {
+    if self.configs.is_empty() {
+      return None;
+    }
+    self.select((self.current + 1) % self.configs.len())
+  }
+  pub fn select_prev(&mut self) -> Option<&TransportConfig> {
+    if self.configs.is_empty() {
+      return None;
+    }
+    self.select((self.current + self.configs.len() - 1) % self.configs.len())
+  }
+  // the transport to reconnect with once a switch command's response is out
+  pub fn take_switch(&mut self) -> Option<TransportConfig> {
+    if !self.switch_pending {
+      return None;
+    }
+    self.switch_pending = false;
+    self.current().cloned()
+  }
+  // selects the transport with config's url, which is added or, when the url
+  // is already listed, takes over that entry's options
+  fn change(&mut self, config: TransportConfig) {
+    let url = config.url().to_string();
+    self.add_transport_ref(config);
+    if let Some(index) = self.find(&url) {
+      self.select(index);
+      self.switch_pending = true;
+    }
+  }
+  pub fn to_tlvs(&self) -> Result<Vec<Tlv>, ProtocolError> {
+    self.configs.iter().map(TransportConfig::to_tlv).collect()
+  }
+  // the transport_* commands, None for any other method
+  pub fn handle(&mut self, request: &Packet, response: &mut Packet) -> Option<Result<(), CommandError>> {
+    let method = request.find_tlv(TlvType::Method)?.as_str().ok()?;
+    let result = match method {
+      "transport_add" => TransportConfig::from_tlvs(request.payload().iter().flatten())
+        .map(|config| self.add_transport_ref(config))
+        .map_err(CommandError::from),
+      "transport_change" => TransportConfig::from_tlvs(request.payload().iter().flatten())
+        .map(|config| self.change(config))
+        .map_err(CommandError::from),
+      "transport_next" | "transport_prev" if self.configs.len() < 2 =>
+        Err(CommandError::new(ERROR_INVALID_PARAMETER, "no other transport to switch to")),
+      "transport_next" => {
+        self.select_next();
+        self.switch_pending = true;
+        Ok(())
+      },
+      "transport_prev" => {
+        self.select_prev();
+        self.switch_pending = true;
+        Ok(())
+      },
+      "transport_list" => self.to_tlvs()
+        .map(|groups| groups.into_iter().for_each(|group| response.add_tlv_ref(group)))
+        .map_err(CommandError::from),
+      _ => return None,
+    };
+    Some(result)
+  }
+}
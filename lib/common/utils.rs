@@ -1,42 +1,57 @@
 use alloc::vec::*;
 use core::mem::size_of;
 
-pub fn slice_to_u32_ntoh(val: &[u8]) -> u32 {
-  if val.len() < size_of::<u32>() {
-    panic!{"slice_to_u32 slice too small: {}", val.len()};
-  }
-  // net to host conversion
-  (val[3] as u32).rotate_left(24) | //hi bits
-  (val[2] as u32).rotate_left(16) |
-  (val[1] as u32).rotate_left(8) |
-  (val[0] as u32)                   //low bits
+use super::error::ProtocolError;
+
+// source of key material, supplied by the caller since no_std has no rng
+pub trait Rng {
+  fn fill_bytes(&mut self, dest: &mut [u8]);
 }
-pub fn slice_to_u32_hton(val: &[u8]) -> u32 {
-  if val.len() < size_of::<u32>() {
-    panic!{"slice_to_u32 slice too small: {}", val.len()};
-  }
+
+pub fn slice_to_u32_ntoh(val: &[u8]) -> Result<u32, ProtocolError> {
+  ProtocolError::check_len(val, size_of::<u32>())?;
+  // net to host conversion, the wire is big endian
+  Ok((val[0] as u32).rotate_left(24) | //hi bits
+  (val[1] as u32).rotate_left(16) |
+  (val[2] as u32).rotate_left(8) |
+  (val[3] as u32))                     //low bits
+}
+pub fn slice_to_u32_hton(val: &[u8]) -> Result<u32, ProtocolError> {
+  ProtocolError::check_len(val, size_of::<u32>())?;
   // net to host conversion
-  (val[0] as u32).rotate_left(24) | //hi bits
+  Ok((val[0] as u32).rotate_left(24) | //hi bits
   (val[1] as u32).rotate_left(16) |
   (val[2] as u32).rotate_left(8) |
-  (val[3] as u32)                   //low bits
+  (val[3] as u32))                     //low bits
+}
+pub fn slice_to_u64_ntoh(val: &[u8]) -> Result<u64, ProtocolError> {
+  ProtocolError::check_len(val, size_of::<u64>())?;
+  // high word first, as meterpreter's htonq writes it
+  let hi = slice_to_u32_ntoh(&val[..4])? as u64;
+  let lo = slice_to_u32_ntoh(&val[4..])? as u64;
+  Ok(hi.rotate_left(32) | lo)
 }
 
 pub fn u32_to_vec_hton(val: u32) -> Vec<u8> {
-  let mut vec: Vec<u8> = Vec::with_capacity(4);
   // host to net conversion
-  vec.push((val & 0xFF) as u8);
-  vec.push(((val & 0xFF00).rotate_right(8)) as u8);
-  vec.push(((val & 0xFF0000).rotate_right(16)) as u8);
-  vec.push(((val & 0xFF000000).rotate_right(24)) as u8);
-  vec
+  [
+    ((val & 0xFF000000).rotate_right(24)) as u8,
+    ((val & 0xFF0000).rotate_right(16)) as u8,
+    ((val & 0xFF00).rotate_right(8)) as u8,
+    (val & 0xFF) as u8,
+  ].to_vec()
 }
 pub fn u32_to_vec_ntoh(val: u32) -> Vec<u8> {
-  let mut vec: Vec<u8> = Vec::with_capacity(4);
   // net to host conversion
-  vec.push(((val & 0xFF000000).rotate_right(24)) as u8);
-  vec.push(((val & 0xFF0000).rotate_right(16)) as u8);
-  vec.push(((val & 0xFF00).rotate_right(8)) as u8);
-  vec.push((val & 0xFF) as u8);
+  [
+    ((val & 0xFF000000).rotate_right(24)) as u8,
+    ((val & 0xFF0000).rotate_right(16)) as u8,
+    ((val & 0xFF00).rotate_right(8)) as u8,
+    (val & 0xFF) as u8,
+  ].to_vec()
+}
+pub fn u64_to_vec_hton(val: u64) -> Vec<u8> {
+  let mut vec: Vec<u8> = u32_to_vec_hton(val.rotate_right(32) as u32);
+  vec.append(&mut u32_to_vec_hton(val as u32));
   vec
 }
@@ -1,5 +1,4 @@
-#![no_std]
-#![feature(alloc)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(unused_macros, dead_code)]
 
 extern crate alloc;